@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState
+{
+    /// Requests flow and outcomes are counted.
+    Closed,
+    /// Requests fail fast with `Error::CircuitOpen`.
+    Open,
+    /// A limited number of probe requests decide between `Closed` and `Open`.
+    HalfOpen
+}
+
+/// Fails fast while a downstream service is failing, see `EndpointBuilder::circuit_breaker`.
+pub struct CircuitBreaker {
+    consecutive_failures: usize,
+    failure_rate: Option<(f64, usize)>,
+    open_for: Duration,
+    probes: usize,
+    is_failure: Arc<Fn(u16) -> bool + Send + Sync>,
+    listeners: Vec<Arc<Fn(CircuitState, CircuitState) + Send + Sync>>,
+    state: Mutex<State>
+}
+
+pub struct CircuitBreakerBuilder {
+    consecutive_failures: usize,
+    failure_rate: Option<(f64, usize)>,
+    open_for: Duration,
+    probes: usize,
+    is_failure: Arc<Fn(u16) -> bool + Send + Sync>,
+    listeners: Vec<Arc<Fn(CircuitState, CircuitState) + Send + Sync>>
+}
+
+struct State {
+    circuit: CircuitState,
+    consecutive: usize,
+    outcomes: VecDeque<bool>,
+    opened_at: Option<Instant>,
+    in_flight: usize,
+    successes: usize
+}
+
+impl CircuitBreaker {
+    pub fn configure() -> CircuitBreakerBuilder {
+        CircuitBreakerBuilder {
+            consecutive_failures: 5,
+            failure_rate: None,
+            open_for: Duration::from_secs(30),
+            probes: 1,
+            is_failure: Arc::new(|status| status >= 500 || status == 408),
+            listeners: Vec::new()
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state.lock().unwrap().circuit
+    }
+
+    /// Lets a request through or fails it with `Error::CircuitOpen`.
+    pub fn allow(&self) -> Result<(), Error> {
+        let (allowed, transition) = {
+            let mut state = self.state.lock().unwrap();
+            let mut transition = None;
+            if state.circuit == CircuitState::Open {
+                if state.opened_at.map_or(false, |opened| opened.elapsed() < self.open_for) {
+                    return Err(Error::CircuitOpen);
+                }
+                transition = Some(state.switch(CircuitState::HalfOpen));
+            }
+            let allowed = state.circuit != CircuitState::HalfOpen || state.in_flight < self.probes;
+            if allowed && state.circuit == CircuitState::HalfOpen {
+                state.in_flight += 1;
+            }
+            (allowed, transition)
+        };
+        self.notify(transition);
+        if allowed { Ok(()) } else { Err(Error::CircuitOpen) }
+    }
+
+    /// Records a response status.
+    pub fn record_status(&self, status: u16) {
+        let failed = (self.is_failure)(status);
+        self.record(!failed);
+    }
+
+    /// Records a request that failed without a response. Only connection and
+    /// IO errors, timeouts included, say something about the downstream service;
+    /// anything else just gives back the probe slot it held.
+    pub fn record_error(&self, err: &Error) {
+        if is_failure(err) {
+            self.record(false);
+        } else {
+            self.release();
+        }
+    }
+
+    /// Forgets a request that was let through but never reached the service.
+    pub fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.circuit == CircuitState::HalfOpen {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+    }
+
+    /// Records the outcome of a request that was let through.
+    pub fn record(&self, success: bool) {
+        let transition = {
+            let mut state = self.state.lock().unwrap();
+            match state.circuit {
+                CircuitState::HalfOpen => {
+                    state.in_flight = state.in_flight.saturating_sub(1);
+                    if !success {
+                        Some(state.switch(CircuitState::Open))
+                    } else {
+                        state.successes += 1;
+                        if state.successes >= self.probes {
+                            Some(state.switch(CircuitState::Closed))
+                        } else {
+                            None
+                        }
+                    }
+                },
+                CircuitState::Closed => {
+                    state.consecutive = if success { 0 } else { state.consecutive + 1 };
+                    if let Some((_, window)) = self.failure_rate {
+                        state.outcomes.push_back(success);
+                        while state.outcomes.len() > window {
+                            state.outcomes.pop_front();
+                        }
+                    }
+                    if self.tripped(&state) {
+                        Some(state.switch(CircuitState::Open))
+                    } else {
+                        None
+                    }
+                },
+                // a request let through before the circuit opened
+                CircuitState::Open => None
+            }
+        };
+        self.notify(transition);
+    }
+
+    fn tripped(&self, state: &State) -> bool {
+        if self.consecutive_failures > 0 && state.consecutive >= self.consecutive_failures {
+            return true;
+        }
+        match self.failure_rate {
+            Some((rate, window)) if state.outcomes.len() >= window => {
+                let failures = state.outcomes.iter().filter(|success| !**success).count();
+                failures as f64 / window as f64 >= rate
+            },
+            _ => false
+        }
+    }
+
+    fn notify(&self, transition: Option<(CircuitState, CircuitState)>) {
+        if let Some((from, to)) = transition {
+            info!("Circuit breaker {:?} -> {:?}", from, to);
+            for listener in self.listeners.iter() {
+                listener(from, to);
+            }
+        }
+    }
+}
+
+/// Whether `err` tells that the service failed rather than the request itself.
+pub fn is_failure(err: &Error) -> bool {
+    match *err {
+        Error::IoError(_) | Error::HttpError(_) => true,
+        _ => false
+    }
+}
+
+impl State {
+    fn switch(&mut self, to: CircuitState) -> (CircuitState, CircuitState) {
+        let from = self.circuit;
+        self.circuit = to;
+        self.consecutive = 0;
+        self.outcomes.clear();
+        self.in_flight = 0;
+        self.successes = 0;
+        self.opened_at = if to == CircuitState::Open { Some(Instant::now()) } else { None };
+        (from, to)
+    }
+}
+
+impl CircuitBreakerBuilder {
+    /// Opens after `failures` failures in a row (5 by default, 0 disables).
+    pub fn consecutive_failures(&mut self, failures: usize) -> &mut CircuitBreakerBuilder {
+        self.consecutive_failures = failures;
+        self
+    }
+
+    /// Opens once at least `rate` (0.0 to 1.0) of the last `window` requests failed.
+    pub fn failure_rate(&mut self, rate: f64, window: usize) -> &mut CircuitBreakerBuilder {
+        self.failure_rate = Some((rate, window));
+        self
+    }
+
+    /// How long to fail fast before probing (30 seconds by default).
+    pub fn open_for(&mut self, duration: Duration) -> &mut CircuitBreakerBuilder {
+        self.open_for = duration;
+        self
+    }
+
+    /// How many successful probes close the circuit again (1 by default).
+    pub fn half_open_probes(&mut self, probes: usize) -> &mut CircuitBreakerBuilder {
+        self.probes = ::std::cmp::max(probes, 1);
+        self
+    }
+
+    /// Which statuses count as failures; by default 5xx and 408 Request Timeout.
+    pub fn failure_status<F: Fn(u16) -> bool + Send + Sync + 'static>(&mut self, is_failure: F) -> &mut CircuitBreakerBuilder {
+        self.is_failure = Arc::new(is_failure);
+        self
+    }
+
+    /// Called with the old and the new state on every transition.
+    pub fn on_state_change<F: Fn(CircuitState, CircuitState) + Send + Sync + 'static>(&mut self, listener: F) -> &mut CircuitBreakerBuilder {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
+    pub fn build(&self) -> CircuitBreaker {
+        CircuitBreaker {
+            consecutive_failures: self.consecutive_failures,
+            failure_rate: self.failure_rate,
+            open_for: self.open_for,
+            probes: self.probes,
+            is_failure: self.is_failure.clone(),
+            listeners: self.listeners.clone(),
+            state: Mutex::new(State {
+                circuit: CircuitState::Closed,
+                consecutive: 0,
+                outcomes: VecDeque::new(),
+                opened_at: None,
+                in_flight: 0,
+                successes: 0
+            })
+        }
+    }
+}
+
+#[test]
+fn circuit_breaker_consecutive() {
+    let breaker = CircuitBreaker::configure()
+        .consecutive_failures(2)
+        .open_for(Duration::from_millis(0))
+        .build();
+
+    breaker.record(false);
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    breaker.record_status(503);
+    assert_eq!(breaker.state(), CircuitState::Open);
+
+    // open_for has passed, so the next request is a probe
+    assert!(breaker.allow().is_ok());
+    assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    assert!(breaker.allow().is_err());
+    breaker.record_status(200);
+    assert_eq!(breaker.state(), CircuitState::Closed);
+}
+
+#[test]
+fn circuit_breaker_failure_rate() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let transitions = Arc::new(AtomicUsize::new(0));
+    let counter = transitions.clone();
+    let breaker = CircuitBreaker::configure()
+        .consecutive_failures(0)
+        .failure_rate(0.5, 4)
+        .on_state_change(move |_, _| { counter.fetch_add(1, Ordering::SeqCst); })
+        .build();
+
+    breaker.record(false);
+    breaker.record(true);
+    breaker.record(false);
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    breaker.record(true);
+    assert_eq!(breaker.state(), CircuitState::Open);
+    assert!(breaker.allow().is_err());
+    assert_eq!(transitions.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn circuit_breaker_errors() {
+    let breaker = CircuitBreaker::configure()
+        .consecutive_failures(1)
+        .open_for(Duration::from_millis(0))
+        .build();
+
+    breaker.record_status(404);
+    breaker.record_status(429);
+    breaker.record_error(&Error::BodyNotReplayable);
+    breaker.record_error(&Error::Codec("bad value".to_string()));
+    assert_eq!(breaker.state(), CircuitState::Closed);
+    breaker.record_error(&Error::from(::std::io::Error::new(::std::io::ErrorKind::ConnectionRefused, "refused")));
+    assert_eq!(breaker.state(), CircuitState::Open);
+
+    // a probe that fails for its own reasons frees the slot for the next one
+    assert!(breaker.allow().is_ok());
+    breaker.record_error(&Error::BodyNotReplayable);
+    assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    assert!(breaker.allow().is_ok());
+}
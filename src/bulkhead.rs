@@ -0,0 +1,114 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use error::Error;
+
+/// Caps the requests one endpoint has in flight, queueing the rest for a while.
+pub struct Bulkhead {
+    max_concurrency: usize,
+    queue_size: usize,
+    queue_timeout: Duration,
+    state: Mutex<Load>,
+    released: Condvar
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Load {
+    in_flight: usize,
+    queued: usize
+}
+
+/// A slot in the bulkhead, given back when dropped.
+pub struct Permit {
+    bulkhead: Arc<Bulkhead>
+}
+
+impl Bulkhead {
+    pub fn new(max_concurrency: usize, queue_size: usize, queue_timeout: Duration) -> Bulkhead {
+        Bulkhead {
+            max_concurrency: ::std::cmp::max(max_concurrency, 1),
+            queue_size: queue_size,
+            queue_timeout: queue_timeout,
+            state: Mutex::new(Load { in_flight: 0, queued: 0 }),
+            released: Condvar::new()
+        }
+    }
+
+    /// Waits for a free slot, failing with `Error::BulkheadFull` when the queue
+    /// is full or the queue timeout runs out.
+    pub fn acquire(bulkhead: &Arc<Bulkhead>) -> Result<Permit, Error> {
+        let mut state = bulkhead.state.lock().unwrap();
+        if state.in_flight >= bulkhead.max_concurrency {
+            if state.queued >= bulkhead.queue_size {
+                return Err(Error::BulkheadFull(state.in_flight, state.queued));
+            }
+            state.queued += 1;
+            let deadline = Instant::now() + bulkhead.queue_timeout;
+            while state.in_flight >= bulkhead.max_concurrency {
+                let now = Instant::now();
+                if now >= deadline {
+                    state.queued -= 1;
+                    return Err(Error::BulkheadFull(state.in_flight, state.queued));
+                }
+                state = bulkhead.released.wait_timeout(state, deadline - now).unwrap().0;
+            }
+            state.queued -= 1;
+        }
+        state.in_flight += 1;
+        Ok(Permit { bulkhead: bulkhead.clone() })
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().queued
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.bulkhead.state.lock().unwrap();
+        state.in_flight -= 1;
+        self.bulkhead.released.notify_one();
+    }
+}
+
+#[test]
+fn bulkhead_rejects() {
+    let bulkhead = Arc::new(Bulkhead::new(1, 1, Duration::from_millis(10)));
+
+    let permit = Bulkhead::acquire(&bulkhead).unwrap();
+    assert_eq!(bulkhead.in_flight(), 1);
+
+    // one caller may queue, but times out while the permit is held
+    match Bulkhead::acquire(&bulkhead) {
+        Err(Error::BulkheadFull(1, 0)) => {},
+        _ => panic!("expected the queued request to time out")
+    }
+
+    drop(permit);
+    assert_eq!(bulkhead.in_flight(), 0);
+    assert!(Bulkhead::acquire(&bulkhead).is_ok());
+}
+
+#[test]
+fn bulkhead_queue() {
+    use std::thread;
+
+    let bulkhead = Arc::new(Bulkhead::new(1, 1, Duration::from_secs(5)));
+    let permit = Bulkhead::acquire(&bulkhead).unwrap();
+
+    let waiter = bulkhead.clone();
+    let handle = thread::spawn(move || Bulkhead::acquire(&waiter).map(|_| ()));
+    while bulkhead.queued() == 0 {
+        thread::yield_now();
+    }
+
+    // the queue is full now
+    assert!(Bulkhead::acquire(&bulkhead).is_err());
+
+    drop(permit);
+    assert!(handle.join().unwrap().is_ok());
+}
@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use hyper::header::Headers;
+use hyper::status::StatusCode;
+use openssl::crypto::hash::{hash, Type};
+use serde_json::{self, Map, Value};
+use time;
+use url::Url;
+
+use http::Response;
+
+static TEMPORARY_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Where cached responses live. Implementations must be safe to share between threads.
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    fn remove(&self, key: &str);
+}
+
+/// A stored response together with what is needed to judge and revalidate it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheEntry {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    stored_at: i64,
+    max_age: i64,
+    stale_while_revalidate: i64,
+    vary: Vec<(String, Option<String>)>
+}
+
+/// How a cached entry may be used right now.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freshness {
+    Fresh,
+    /// Stale, but may be served while it is revalidated in the background.
+    StaleWhileRevalidate,
+    Stale
+}
+
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, CacheEntry>>
+}
+
+/// Stores each entry in a directory as one `<sha256 of key>.entry` file: a line
+/// of JSON metadata, including the key, followed by the body.
+pub struct DiskCache {
+    dir: PathBuf
+}
+
+/// Cache-Control directives this crate acts on.
+#[derive(Debug, Default, PartialEq)]
+struct Directives {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<i64>,
+    stale_while_revalidate: Option<i64>
+}
+
+pub fn now() -> i64 {
+    time::get_time().sec
+}
+
+pub fn key(url: &Url) -> String {
+    url.to_string()
+}
+
+/// Whether the request asks for its cached copy to be revalidated first.
+pub fn no_cache(request_headers: &HashMap<String, String>) -> bool {
+    Directives::parse(lookup(request_headers, "Cache-Control").as_ref().map(|v| v.as_str())).no_cache
+}
+
+impl CacheEntry {
+    /// Builds an entry for a GET response, or `None` if it must not be stored.
+    pub fn from_response(response: &Response, request_headers: &HashMap<String, String>) -> Option<CacheEntry> {
+        if response.status != StatusCode::Ok {
+            return None;
+        }
+
+        let headers = header_list(&response.headers);
+        let directives = Directives::parse(header(&headers, "Cache-Control"));
+        let requested = Directives::parse(lookup(request_headers, "Cache-Control").as_ref().map(|v| v.as_str()));
+        if directives.no_store || requested.no_store {
+            return None;
+        }
+
+        let max_age = if directives.no_cache {
+            Some(0)
+        } else {
+            directives.max_age.or_else(|| expires_in(&headers))
+        };
+        let validator = header(&headers, "ETag").is_some() || header(&headers, "Last-Modified").is_some();
+        let max_age = match max_age {
+            Some(max_age) => max_age,
+            None if validator => 0,
+            None => return None
+        };
+
+        let mut vary = Vec::new();
+        if let Some(names) = header(&headers, "Vary") {
+            for name in names.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+                if name == "*" {
+                    return None;
+                }
+                vary.push((name.to_lowercase(), lookup(request_headers, name)));
+            }
+        }
+
+        Some(CacheEntry {
+            status: response.status.to_u16(),
+            headers: headers,
+            body: response.body.clone().unwrap_or(Vec::new()),
+            stored_at: now(),
+            max_age: max_age,
+            stale_while_revalidate: directives.stale_while_revalidate.unwrap_or(0),
+            vary: vary
+        })
+    }
+
+    /// Whether the request headers select this entry under its `Vary` rules.
+    pub fn matches(&self, request_headers: &HashMap<String, String>) -> bool {
+        self.vary.iter().all(|&(ref name, ref value)| lookup(request_headers, name) == *value)
+    }
+
+    pub fn freshness(&self, now: i64) -> Freshness {
+        let age = now - self.stored_at;
+        if age < self.max_age {
+            Freshness::Fresh
+        } else if age < self.max_age + self.stale_while_revalidate {
+            Freshness::StaleWhileRevalidate
+        } else {
+            Freshness::Stale
+        }
+    }
+
+    /// Headers that turn a request into a conditional one.
+    pub fn validators(&self) -> Vec<(&'static str, String)> {
+        let mut validators = Vec::new();
+        if let Some(etag) = header(&self.headers, "ETag") {
+            validators.push(("If-None-Match", etag.to_string()));
+        }
+        if let Some(last_modified) = header(&self.headers, "Last-Modified") {
+            validators.push(("If-Modified-Since", last_modified.to_string()));
+        }
+        validators
+    }
+
+    /// Folds a `304 Not Modified` into this entry: new headers win, freshness restarts.
+    pub fn refresh(&mut self, not_modified: &Response) {
+        let updated = header_list(&not_modified.headers);
+        for &(ref name, ref value) in updated.iter() {
+            self.headers.retain(|&(ref existing, _)| existing.to_lowercase() != name.to_lowercase());
+            self.headers.push((name.clone(), value.clone()));
+        }
+        let directives = Directives::parse(header(&self.headers, "Cache-Control"));
+        if let Some(max_age) = directives.max_age.or_else(|| expires_in(&self.headers)) {
+            self.max_age = max_age;
+        }
+        self.stored_at = now();
+    }
+
+    pub fn to_response(&self, url: &Url) -> Response {
+        let mut headers = Headers::new();
+        for &(ref name, ref value) in self.headers.iter() {
+            headers.set_raw(name.clone(), vec![value.as_bytes().to_vec()]);
+        }
+        Response {
+            status: StatusCode::from_u16(self.status),
+            headers: headers,
+            body: Some(self.body.clone()),
+            url: url.clone(),
+            redirects: Vec::new()
+        }
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Everything but the body, for stores that persist entries.
+    pub fn metadata(&self) -> Value {
+        let mut metadata = Map::new();
+        metadata.insert("status".to_string(), Value::from(self.status));
+        metadata.insert("headers".to_string(), pairs_to_json(self.headers.iter().map(|&(ref n, ref v)| (n.clone(), Some(v.clone())))));
+        metadata.insert("stored_at".to_string(), Value::from(self.stored_at));
+        metadata.insert("max_age".to_string(), Value::from(self.max_age));
+        metadata.insert("stale_while_revalidate".to_string(), Value::from(self.stale_while_revalidate));
+        metadata.insert("vary".to_string(), pairs_to_json(self.vary.iter().cloned()));
+        Value::Object(metadata)
+    }
+
+    pub fn from_parts(metadata: &Value, body: Vec<u8>) -> Option<CacheEntry> {
+        let headers = try_opt!(pairs_from_json(metadata.get("headers")));
+        Some(CacheEntry {
+            status: try_opt!(metadata.get("status").and_then(|v| v.as_u64())) as u16,
+            headers: headers.into_iter().map(|(name, value)| (name, value.unwrap_or(String::new()))).collect(),
+            body: body,
+            stored_at: try_opt!(metadata.get("stored_at").and_then(|v| v.as_i64())),
+            max_age: try_opt!(metadata.get("max_age").and_then(|v| v.as_i64())),
+            stale_while_revalidate: metadata.get("stale_while_revalidate").and_then(|v| v.as_i64()).unwrap_or(0),
+            vary: try_opt!(pairs_from_json(metadata.get("vary")))
+        })
+    }
+}
+
+impl Directives {
+    fn parse(value: Option<&str>) -> Directives {
+        let mut directives = Directives::default();
+        for directive in value.unwrap_or("").split(',') {
+            let mut parts = directive.trim().splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim().to_lowercase();
+            let argument = parts.next().and_then(|arg| arg.trim().trim_matches('"').parse::<i64>().ok());
+            match name.as_ref() {
+                "no-store" => directives.no_store = true,
+                "no-cache" => directives.no_cache = true,
+                "max-age" => directives.max_age = argument,
+                "stale-while-revalidate" => directives.stale_while_revalidate = argument,
+                _ => {}
+            }
+        }
+        directives
+    }
+}
+
+impl MemoryCache {
+    pub fn new() -> MemoryCache {
+        MemoryCache {
+            entries: Mutex::new(HashMap::new())
+        }
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        self.entries.lock().unwrap().insert(key.to_string(), entry);
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+impl DiskCache {
+    /// Uses `dir`, creating it if needed.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> ::std::io::Result<DiskCache> {
+        let dir = dir.into();
+        try!(fs::create_dir_all(&dir));
+        Ok(DiskCache {
+            dir: dir
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let digest: Vec<String> = hash(Type::SHA256, key.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(format!("{}.entry", digest.concat()))
+    }
+
+    fn read(&self, key: &str) -> ::std::io::Result<Option<CacheEntry>> {
+        let mut reader = BufReader::new(try!(File::open(self.path(key))));
+        let mut line = String::new();
+        try!(reader.read_line(&mut line));
+        let metadata: Value = match serde_json::from_str(&line) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None)
+        };
+        if metadata.get("key").and_then(|v| v.as_str()) != Some(key) {
+            return Ok(None);
+        }
+        let mut body = Vec::new();
+        try!(reader.read_to_end(&mut body));
+        Ok(CacheEntry::from_parts(&metadata, body))
+    }
+
+    /// Writes the entry under a temporary name and renames it into place, so
+    /// readers see either the old entry or the new one, never a mix.
+    fn write(&self, key: &str, entry: &CacheEntry) -> ::std::io::Result<()> {
+        let mut metadata = entry.metadata();
+        if let Value::Object(ref mut map) = metadata {
+            map.insert("key".to_string(), Value::from(key));
+        }
+        let temporary = self.temporary(key);
+        {
+            let mut file = try!(File::create(&temporary));
+            try!(file.write_all(metadata.to_string().as_bytes()));
+            try!(file.write_all(b"\n"));
+            try!(file.write_all(entry.body()));
+        }
+        fs::rename(temporary, self.path(key))
+    }
+
+    fn temporary(&self, key: &str) -> PathBuf {
+        let unique = TEMPORARY_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let mut path = self.path(key).into_os_string();
+        path.push(format!(".{}.{}.tmp", ::std::process::id(), unique));
+        PathBuf::from(path)
+    }
+}
+
+impl CacheStore for DiskCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        match self.read(key) {
+            Ok(entry) => entry,
+            Err(_) => None
+        }
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        if let Err(e) = self.write(key, &entry) {
+            warn!("Could not write cache entry for {}: {}", key, e);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+    }
+}
+
+/// Headers worth keeping with an entry. `Set-Cookie` is left out: cookies were
+/// applied when the response arrived and must not be handed out again.
+fn header_list(headers: &Headers) -> Vec<(String, String)> {
+    headers.iter()
+        .filter(|header| header.name().to_lowercase() != "set-cookie")
+        .map(|header| (header.name().to_string(), header.value_string()))
+        .collect()
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    let name = name.to_lowercase();
+    headers.iter()
+        .find(|&&(ref key, _)| key.to_lowercase() == name)
+        .map(|&(_, ref value)| value.as_ref())
+}
+
+fn lookup(headers: &HashMap<String, String>, name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    headers.iter()
+        .find(|&(key, _)| key.to_lowercase() == name)
+        .map(|(_, value)| value.clone())
+}
+
+/// Seconds until `Expires`, measured against `Date` when the server sent one.
+fn expires_in(headers: &[(String, String)]) -> Option<i64> {
+    let expires = try_opt!(header(headers, "Expires").and_then(http_date));
+    let date = header(headers, "Date").and_then(http_date).unwrap_or(now());
+    Some(::std::cmp::max(expires - date, 0))
+}
+
+pub fn http_date(value: &str) -> Option<i64> {
+    time::strptime(value.trim(), "%a, %d %b %Y %H:%M:%S GMT")
+        .ok()
+        .map(|tm| tm.to_timespec().sec)
+}
+
+fn pairs_to_json<I: Iterator<Item = (String, Option<String>)>>(pairs: I) -> Value {
+    Value::Array(pairs.map(|(name, value)| {
+        Value::Array(vec![Value::from(name), value.map(Value::from).unwrap_or(Value::Null)])
+    }).collect())
+}
+
+fn pairs_from_json(value: Option<&Value>) -> Option<Vec<(String, Option<String>)>> {
+    let mut pairs = Vec::new();
+    for pair in try_opt!(value.and_then(|v| v.as_array())) {
+        let name = try_opt!(pair.get(0).and_then(|v| v.as_str())).to_string();
+        let value = pair.get(1).and_then(|v| v.as_str()).map(|v| v.to_string());
+        pairs.push((name, value));
+    }
+    Some(pairs)
+}
+
+#[test]
+fn cache_directives() {
+    assert_eq!(Directives::parse(Some("public, max-age=60, stale-while-revalidate=30")), Directives {
+        no_store: false,
+        no_cache: false,
+        max_age: Some(60),
+        stale_while_revalidate: Some(30)
+    });
+    assert_eq!(Directives::parse(Some("no-store")).no_store, true);
+    assert_eq!(Directives::parse(None), Directives::default());
+
+    let mut headers = HashMap::new();
+    assert!(!no_cache(&headers));
+    headers.insert("cache-control".to_string(), "no-cache".to_string());
+    assert!(no_cache(&headers));
+    let mut response_headers = Headers::new();
+    response_headers.set_raw("Cache-Control", vec![b"max-age=60".to_vec()]);
+    let response = Response {
+        status: StatusCode::Ok,
+        headers: response_headers,
+        body: Some(b"{}".to_vec()),
+        url: Url::parse("http://example.com/posts").unwrap(),
+        redirects: Vec::new()
+    };
+    assert!(CacheEntry::from_response(&response, &headers).is_some());
+    headers.insert("cache-control".to_string(), "no-store".to_string());
+    assert!(CacheEntry::from_response(&response, &headers).is_none());
+}
+
+#[test]
+fn cache_entry_freshness() {
+    let mut headers = Headers::new();
+    headers.set_raw("Cache-Control", vec![b"max-age=60, stale-while-revalidate=30".to_vec()]);
+    headers.set_raw("ETag", vec![b"\"v1\"".to_vec()]);
+    headers.set_raw("Vary", vec![b"Accept".to_vec()]);
+    let response = Response {
+        status: StatusCode::Ok,
+        headers: headers,
+        body: Some(b"{}".to_vec()),
+        url: Url::parse("http://example.com/posts").unwrap(),
+        redirects: Vec::new()
+    };
+    let mut request_headers = HashMap::new();
+    request_headers.insert("Accept".to_string(), "application/json".to_string());
+
+    let entry = CacheEntry::from_response(&response, &request_headers).unwrap();
+    let stored_at = entry.stored_at;
+
+    assert_eq!(entry.freshness(stored_at + 10), Freshness::Fresh);
+    assert_eq!(entry.freshness(stored_at + 70), Freshness::StaleWhileRevalidate);
+    assert_eq!(entry.freshness(stored_at + 100), Freshness::Stale);
+    assert_eq!(entry.validators(), vec![("If-None-Match", "\"v1\"".to_string())]);
+
+    assert!(entry.matches(&request_headers));
+    request_headers.insert("Accept".to_string(), "application/xml".to_string());
+    assert!(!entry.matches(&request_headers));
+
+    let metadata = entry.metadata();
+    assert_eq!(CacheEntry::from_parts(&metadata, entry.body().to_vec()), Some(entry));
+}
+
+#[test]
+fn cache_entry_set_cookie() {
+    let mut headers = Headers::new();
+    headers.set_raw("Cache-Control", vec![b"max-age=60".to_vec()]);
+    headers.set_raw("Set-Cookie", vec![b"a=1".to_vec(), b"b=2; Path=/".to_vec()]);
+    let response = Response {
+        status: StatusCode::Ok,
+        headers: headers,
+        body: Some(b"{}".to_vec()),
+        url: Url::parse("http://example.com/posts").unwrap(),
+        redirects: Vec::new()
+    };
+    let entry = CacheEntry::from_response(&response, &HashMap::new()).unwrap();
+    let cached = entry.to_response(&response.url);
+
+    assert!(cached.headers.get_raw("Set-Cookie").is_none());
+    assert!(cached.headers.get_raw("Cache-Control").is_some());
+}
+
+#[test]
+fn disk_cache_write() {
+    use std::env;
+
+    let dir = env::temp_dir().join(format!("rest-cache-{}", time::precise_time_ns()));
+    let cache = DiskCache::new(dir.clone()).unwrap();
+    let mut headers = Headers::new();
+    headers.set_raw("Cache-Control", vec![b"max-age=60".to_vec()]);
+    let response = Response {
+        status: StatusCode::Ok,
+        headers: headers,
+        body: Some(b"{}".to_vec()),
+        url: Url::parse("http://example.com/posts").unwrap(),
+        redirects: Vec::new()
+    };
+    let entry = CacheEntry::from_response(&response, &HashMap::new()).unwrap();
+
+    cache.put("posts", entry.clone());
+    cache.put("posts", entry.clone());
+    assert_eq!(cache.get("posts"), Some(entry.clone()));
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    // a file that landed under another key's name is not served for this one
+    fs::rename(cache.path("posts"), cache.path("comments")).unwrap();
+    assert_eq!(cache.get("comments"), None);
+    let _ = fs::remove_dir_all(dir);
+}
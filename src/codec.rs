@@ -0,0 +1,303 @@
+use std::fmt;
+use std::sync::Arc;
+
+use erased_serde;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+#[cfg(feature = "cbor")]
+use serde_cbor;
+#[cfg(feature = "msgpack")]
+use rmp_serde;
+#[cfg(feature = "xml")]
+use serde_xml_rs;
+#[cfg(feature = "yaml")]
+use serde_yaml;
+
+use error::Error;
+
+/// Hands a deserializer to the typed decoding in `Codecs::decode`.
+pub type Visit<'a> = &'a mut FnMut(&mut erased_serde::Deserializer) -> Result<(), erased_serde::Error>;
+
+/// Turns bodies into values and back for one media type.
+///
+/// Codecs work on type-erased serde values so they can be stored as trait
+/// objects; `Codecs::encode` and `Codecs::decode` are the typed entry points.
+pub trait Codec: Send + Sync {
+    /// The media type this codec produces and accepts, e.g. `application/json`.
+    fn content_type(&self) -> &str;
+
+    fn encode(&self, value: &erased_serde::Serialize) -> Result<Vec<u8>, Error>;
+
+    /// Runs `visit` on a deserializer over `body`.
+    fn decode(&self, body: &[u8], visit: Visit) -> Result<(), Error>;
+
+    /// Whether a response with this (parameter free, lowercase) media type can be decoded.
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type == self.content_type()
+    }
+}
+
+pub struct JsonCodec;
+
+#[cfg(feature = "xml")]
+pub struct XmlCodec;
+
+#[cfg(feature = "msgpack")]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "cbor")]
+pub struct CborCodec;
+
+#[cfg(feature = "yaml")]
+pub struct YamlCodec;
+
+/// A request body value, encoded by the endpoint's codec when the request is sent.
+#[derive(Clone)]
+pub struct Payload(Arc<erased_serde::Serialize + Send + Sync>);
+
+/// The codecs registered on an endpoint. The first one is used for request
+/// bodies and is preferred in `Accept`.
+#[derive(Clone)]
+pub struct Codecs {
+    codecs: Vec<Arc<Codec>>
+}
+
+impl Codecs {
+    pub fn new() -> Codecs {
+        Codecs {
+            codecs: vec![Arc::new(JsonCodec)]
+        }
+    }
+
+    /// Makes `codec` the one used for requests.
+    pub fn select(&mut self, codec: Arc<Codec>) {
+        self.codecs.retain(|c| c.content_type() != codec.content_type());
+        self.codecs.insert(0, codec);
+    }
+
+    /// Registers `codec` for decoding responses only.
+    pub fn add(&mut self, codec: Arc<Codec>) {
+        self.codecs.retain(|c| c.content_type() != codec.content_type());
+        self.codecs.push(codec);
+    }
+
+    pub fn primary(&self) -> &Codec {
+        &*self.codecs[0]
+    }
+
+    pub fn accept(&self) -> String {
+        let mut accept = self.primary().content_type().to_string();
+        for codec in self.codecs.iter().skip(1) {
+            accept.push_str(&format!(", {};q=0.9", codec.content_type()));
+        }
+        accept
+    }
+
+    /// Picks the codec for a response `Content-Type`, falling back to the primary one when there is none.
+    pub fn find(&self, content_type: Option<&str>) -> Result<&Codec, Error> {
+        let media_type = match content_type {
+            Some(content_type) => media_type(content_type),
+            None => return Ok(self.primary())
+        };
+        match self.codecs.iter().find(|codec| codec.accepts(&media_type)) {
+            Some(codec) => Ok(&**codec),
+            None => Err(Error::UnsupportedContentType(media_type))
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        self.primary().encode(value)
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, content_type: Option<&str>, body: &[u8]) -> Result<T, Error> {
+        let codec = try!(self.find(content_type));
+        let mut decoded = None;
+        try!(codec.decode(body, &mut |deserializer| {
+            decoded = Some(try!(erased_serde::deserialize::<T>(deserializer)));
+            Ok(())
+        }));
+        decoded.ok_or(Error::Codec(format!("{} decoder produced no value", codec.content_type())))
+    }
+}
+
+impl Payload {
+    pub fn new<T: Serialize + Send + Sync + 'static>(value: T) -> Payload {
+        Payload(Arc::new(value))
+    }
+
+    pub fn encode(&self, codec: &Codec) -> Result<Vec<u8>, Error> {
+        codec.encode(&*self.0)
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Payload")
+    }
+}
+
+fn codec_error<E: ToString>(err: E) -> Error {
+    Error::Codec(err.to_string())
+}
+
+/// `application/json; charset=utf-8` -> `application/json`
+pub fn media_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or("").trim().to_lowercase()
+}
+
+impl Codec for JsonCodec {
+    fn content_type(&self) -> &str {
+        "application/json"
+    }
+
+    fn encode(&self, value: &erased_serde::Serialize) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(&value).map_err(codec_error)
+    }
+
+    fn decode(&self, body: &[u8], visit: Visit) -> Result<(), Error> {
+        let mut deserializer = serde_json::Deserializer::from_slice(body);
+        try!(visit(&mut erased_serde::Deserializer::erase(&mut deserializer)).map_err(codec_error));
+        deserializer.end().map_err(codec_error)
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type == "application/json" || media_type.ends_with("+json")
+    }
+}
+
+#[cfg(feature = "xml")]
+impl Codec for XmlCodec {
+    fn content_type(&self) -> &str {
+        "application/xml"
+    }
+
+    fn encode(&self, value: &erased_serde::Serialize) -> Result<Vec<u8>, Error> {
+        serde_xml_rs::to_string(&value)
+            .map(|xml| xml.into_bytes())
+            .map_err(codec_error)
+    }
+
+    fn decode(&self, body: &[u8], visit: Visit) -> Result<(), Error> {
+        let mut deserializer = serde_xml_rs::Deserializer::new_from_reader(body);
+        visit(&mut erased_serde::Deserializer::erase(&mut deserializer)).map_err(codec_error)
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type == "application/xml" || media_type == "text/xml" || media_type.ends_with("+xml")
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPackCodec {
+    fn content_type(&self) -> &str {
+        "application/msgpack"
+    }
+
+    fn encode(&self, value: &erased_serde::Serialize) -> Result<Vec<u8>, Error> {
+        rmp_serde::to_vec(&value).map_err(codec_error)
+    }
+
+    fn decode(&self, body: &[u8], visit: Visit) -> Result<(), Error> {
+        let mut deserializer = rmp_serde::Deserializer::new(body);
+        visit(&mut erased_serde::Deserializer::erase(&mut deserializer)).map_err(codec_error)
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type == "application/msgpack" || media_type == "application/x-msgpack"
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn content_type(&self) -> &str {
+        "application/cbor"
+    }
+
+    fn encode(&self, value: &erased_serde::Serialize) -> Result<Vec<u8>, Error> {
+        serde_cbor::to_vec(&value).map_err(codec_error)
+    }
+
+    fn decode(&self, body: &[u8], visit: Visit) -> Result<(), Error> {
+        let mut deserializer = serde_cbor::Deserializer::from_slice(body);
+        try!(visit(&mut erased_serde::Deserializer::erase(&mut deserializer)).map_err(codec_error));
+        deserializer.end().map_err(codec_error)
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl Codec for YamlCodec {
+    fn content_type(&self) -> &str {
+        "application/yaml"
+    }
+
+    fn encode(&self, value: &erased_serde::Serialize) -> Result<Vec<u8>, Error> {
+        serde_yaml::to_string(&value)
+            .map(|yaml| yaml.into_bytes())
+            .map_err(codec_error)
+    }
+
+    fn decode(&self, body: &[u8], visit: Visit) -> Result<(), Error> {
+        let deserializer = serde_yaml::Deserializer::from_slice(body);
+        visit(&mut erased_serde::Deserializer::erase(deserializer)).map_err(codec_error)
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type == "application/yaml" || media_type == "application/x-yaml" || media_type == "text/yaml"
+    }
+}
+
+#[test]
+fn codecs_find() {
+    let codecs = Codecs::new();
+
+    assert_eq!(codecs.find(None).unwrap().content_type(), "application/json");
+    assert_eq!(codecs.find(Some("application/problem+json; charset=utf-8")).unwrap().content_type(), "application/json");
+    assert!(codecs.find(Some("text/html")).is_err());
+}
+
+#[test]
+fn codecs_select() {
+    struct TextCodec;
+
+    impl Codec for TextCodec {
+        fn content_type(&self) -> &str {
+            "text/plain"
+        }
+
+        fn encode(&self, value: &erased_serde::Serialize) -> Result<Vec<u8>, Error> {
+            serde_json::to_string(&value).map(|text| text.into_bytes()).map_err(codec_error)
+        }
+
+        fn decode(&self, body: &[u8], visit: Visit) -> Result<(), Error> {
+            use serde::de::IntoDeserializer;
+            use serde::de::value::{Error as ValueError, StrDeserializer};
+
+            let text = String::from_utf8_lossy(body);
+            let deserializer: StrDeserializer<ValueError> = text.as_ref().into_deserializer();
+            visit(&mut erased_serde::Deserializer::erase(deserializer)).map_err(codec_error)
+        }
+    }
+
+    let mut codecs = Codecs::new();
+    codecs.select(Arc::new(TextCodec));
+
+    assert_eq!(codecs.accept(), "text/plain, application/json;q=0.9");
+    assert_eq!(codecs.decode::<String>(Some("text/plain"), b"hello").unwrap(), "hello");
+}
+
+#[test]
+fn codecs_typed_roundtrip() {
+    use std::collections::BTreeMap;
+
+    // decoded straight into the target type, keys included
+    let mut scores = BTreeMap::new();
+    scores.insert(1u32, "one".to_string());
+
+    let codecs = Codecs::new();
+    let body = codecs.encode(&scores).unwrap();
+    assert_eq!(body, br#"{"1":"one"}"#.to_vec());
+    assert_eq!(codecs.decode::<BTreeMap<u32, String>>(None, &body).unwrap(), scores);
+    assert!(codecs.decode::<u32>(None, b"1 2").is_err());
+}
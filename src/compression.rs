@@ -0,0 +1,182 @@
+use std::io::{self, Write};
+
+use brotli::{CompressorWriter, DecompressorWriter};
+use flate2::Compression;
+use flate2::write::{GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder};
+
+/// Value sent in `Accept-Encoding` when decompression is enabled.
+pub const ACCEPT_ENCODING: &'static str = "gzip, deflate, br";
+
+/// Request bodies smaller than this are not worth compressing.
+pub const DEFAULT_THRESHOLD: usize = 1024;
+
+/// A `Content-Encoding` this crate knows how to handle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding
+{
+    Identity,
+    Gzip,
+    Deflate,
+    Brotli
+}
+
+impl Encoding {
+    pub fn from_header(value: &str) -> Option<Encoding> {
+        match value.trim().to_lowercase().as_ref() {
+            "identity" | "" => Some(Encoding::Identity),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            "br" => Some(Encoding::Brotli),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Brotli => "br"
+        }
+    }
+}
+
+/// Compresses a whole request body.
+pub fn encode(encoding: Encoding, data: &[u8]) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Identity => Ok(data.to_vec()),
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            try!(encoder.write_all(data));
+            encoder.finish()
+        },
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            try!(encoder.write_all(data));
+            encoder.finish()
+        },
+        Encoding::Brotli => {
+            let mut encoder = CompressorWriter::new(Vec::new(), 4096, 6, 22);
+            try!(encoder.write_all(data));
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+/// Passes decoded bytes on to `inner` and refuses to go past `limit`.
+pub struct Limited<W: Write> {
+    inner: W,
+    written: usize,
+    limit: Option<usize>,
+    exceeded: bool
+}
+
+impl<W: Write> Limited<W> {
+    pub fn new(inner: W, limit: Option<usize>) -> Limited<W> {
+        Limited {
+            inner: inner,
+            written: 0,
+            limit: limit,
+            exceeded: false
+        }
+    }
+}
+
+impl<W: Write> Write for Limited<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.written + data.len() > limit {
+                self.exceeded = true;
+                return Err(io::Error::new(io::ErrorKind::Other, "response body exceeds size limit"));
+            }
+        }
+        try!(self.inner.write_all(data));
+        self.written += data.len();
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decodes a response body as it is read off the wire and writes it to `W`.
+pub enum BodyDecoder<W: Write> {
+    Identity(Limited<W>),
+    Gzip(GzDecoder<Limited<W>>),
+    Deflate(ZlibDecoder<Limited<W>>),
+    Brotli(DecompressorWriter<Limited<W>>)
+}
+
+impl<W: Write> BodyDecoder<W> {
+    pub fn new(encoding: Encoding, inner: W, limit: Option<usize>) -> BodyDecoder<W> {
+        let sink = Limited::new(inner, limit);
+        match encoding {
+            Encoding::Identity => BodyDecoder::Identity(sink),
+            Encoding::Gzip => BodyDecoder::Gzip(GzDecoder::new(sink)),
+            Encoding::Deflate => BodyDecoder::Deflate(ZlibDecoder::new(sink)),
+            Encoding::Brotli => BodyDecoder::Brotli(DecompressorWriter::new(sink, 4096))
+        }
+    }
+
+    /// Whether the decoded body hit the size limit.
+    pub fn exceeded(&self) -> bool {
+        match *self {
+            BodyDecoder::Identity(ref sink) => sink.exceeded,
+            BodyDecoder::Gzip(ref decoder) => decoder.get_ref().exceeded,
+            BodyDecoder::Deflate(ref decoder) => decoder.get_ref().exceeded,
+            BodyDecoder::Brotli(ref decoder) => decoder.get_ref().exceeded
+        }
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        let sink = match self {
+            BodyDecoder::Identity(sink) => sink,
+            BodyDecoder::Gzip(decoder) => try!(decoder.finish()),
+            BodyDecoder::Deflate(decoder) => try!(decoder.finish()),
+            BodyDecoder::Brotli(decoder) => match decoder.into_inner() {
+                Ok(sink) => sink,
+                Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated brotli stream"))
+            }
+        };
+        Ok(sink.inner)
+    }
+}
+
+impl<W: Write> Write for BodyDecoder<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match *self {
+            BodyDecoder::Identity(ref mut sink) => sink.write(data),
+            BodyDecoder::Gzip(ref mut decoder) => decoder.write(data),
+            BodyDecoder::Deflate(ref mut decoder) => decoder.write(data),
+            BodyDecoder::Brotli(ref mut decoder) => decoder.write(data)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            BodyDecoder::Identity(ref mut sink) => sink.flush(),
+            BodyDecoder::Gzip(ref mut decoder) => decoder.flush(),
+            BodyDecoder::Deflate(ref mut decoder) => decoder.flush(),
+            BodyDecoder::Brotli(ref mut decoder) => decoder.flush()
+        }
+    }
+}
+
+#[test]
+fn body_decoder_gzip() {
+    let compressed = encode(Encoding::Gzip, b"{\"id\": 1}").unwrap();
+
+    let mut decoder = BodyDecoder::new(Encoding::Gzip, Vec::new(), None);
+    decoder.write_all(&compressed).unwrap();
+    assert_eq!(decoder.finish().unwrap(), b"{\"id\": 1}".to_vec());
+}
+
+#[test]
+fn body_decoder_limit() {
+    let compressed = encode(Encoding::Gzip, &[0; 64 * 1024]).unwrap();
+
+    let mut decoder = BodyDecoder::new(Encoding::Gzip, Vec::new(), Some(1024));
+    assert!(decoder.write_all(&compressed).and_then(|_| decoder.flush()).is_err());
+    assert_eq!(decoder.exceeded(), true);
+}
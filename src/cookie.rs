@@ -0,0 +1,307 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_json::{self, Map, Value};
+use time;
+use url::{Host, Url};
+
+use cache;
+
+/// A cookie as stored in a `CookieJar`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    /// Set when the cookie had no `Domain` and only goes back to the exact host.
+    pub host_only: bool,
+    pub path: String,
+    /// Expiry in seconds since the epoch, `None` for session cookies.
+    pub expires: Option<i64>,
+    pub secure: bool,
+    pub http_only: bool
+}
+
+/// Keeps the cookies an endpoint receives and sends them back (RFC 6265).
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>
+}
+
+impl Cookie {
+    /// Parses a `Set-Cookie` value received from `url`. Returns `None` for
+    /// malformed cookies and ones `url` may not set.
+    pub fn parse(set_cookie: &str, url: &Url) -> Option<Cookie> {
+        let host = try_opt!(url.host_str()).to_lowercase();
+        let mut parts = set_cookie.split(';');
+
+        let mut pair = try_opt!(parts.next()).splitn(2, '=');
+        let name = pair.next().unwrap_or("").trim();
+        let value = try_opt!(pair.next()).trim().trim_matches('"');
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url.path()),
+            expires: None,
+            secure: false,
+            http_only: false
+        };
+        let mut max_age = None;
+
+        for attribute in parts {
+            let mut pair = attribute.splitn(2, '=');
+            let key = pair.next().unwrap_or("").trim().to_lowercase();
+            let value = pair.next().unwrap_or("").trim();
+            match key.as_str() {
+                "expires" => cookie.expires = cookie_date(value).or(cookie.expires),
+                "max-age" => max_age = value.parse::<i64>().ok().or(max_age),
+                "domain" => {
+                    let domain = value.trim_left_matches('.').to_lowercase();
+                    // a single label like `com` would hand the cookie to a whole TLD;
+                    // on a host that is itself one label it stays host-only
+                    if !domain.is_empty() && !(domain == host && !domain.contains('.')) {
+                        if !domain_matches(&host, &domain) || is_ip(&host) && host != domain || !domain.contains('.') {
+                            return None;
+                        }
+                        cookie.domain = domain;
+                        cookie.host_only = false;
+                    }
+                },
+                "path" => if value.starts_with('/') {
+                    cookie.path = value.to_string();
+                },
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+
+        // Max-Age wins over Expires
+        if let Some(max_age) = max_age {
+            cookie.expires = Some(if max_age <= 0 { 0 } else { cache::now() + max_age });
+        }
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    /// Whether the cookie should be sent with a request to `url`.
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return false
+        };
+        let domain = if self.host_only { host == self.domain } else { domain_matches(&host, &self.domain) };
+        domain && path_matches(url.path(), &self.path) && (!self.secure || url.scheme() == "https")
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("name".to_string(), Value::from(self.name.clone()));
+        object.insert("value".to_string(), Value::from(self.value.clone()));
+        object.insert("domain".to_string(), Value::from(self.domain.clone()));
+        object.insert("host_only".to_string(), Value::from(self.host_only));
+        object.insert("path".to_string(), Value::from(self.path.clone()));
+        object.insert("expires".to_string(), self.expires.map(Value::from).unwrap_or(Value::Null));
+        object.insert("secure".to_string(), Value::from(self.secure));
+        object.insert("http_only".to_string(), Value::from(self.http_only));
+        Value::Object(object)
+    }
+
+    fn from_json(value: &Value) -> Option<Cookie> {
+        let string = |key: &str| value.get(key).and_then(|v| v.as_str()).map(|v| v.to_string());
+        let flag = |key: &str| value.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+        Some(Cookie {
+            name: try_opt!(string("name")),
+            value: try_opt!(string("value")),
+            domain: try_opt!(string("domain")),
+            host_only: flag("host_only"),
+            path: string("path").unwrap_or("/".to_string()),
+            expires: value.get("expires").and_then(|v| v.as_i64()),
+            secure: flag("secure"),
+            http_only: flag("http_only")
+        })
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar {
+            cookies: Mutex::new(Vec::new())
+        }
+    }
+
+    /// Reads a jar written by `save`. Session cookies are kept, so a script can
+    /// pick up where the previous run left off.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<CookieJar> {
+        let value: Value = try!(serde_json::from_reader(try!(File::open(path)))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())));
+        let now = cache::now();
+        let cookies = value.as_array()
+            .map(|cookies| cookies.iter().filter_map(Cookie::from_json).filter(|c| !c.is_expired(now)).collect())
+            .unwrap_or(Vec::new());
+        Ok(CookieJar {
+            cookies: Mutex::new(cookies)
+        })
+    }
+
+    /// Writes the jar to a temporary file only the owner can read and renames it
+    /// over `path`, so a crash never leaves a truncated jar behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let now = cache::now();
+        let cookies: Vec<Value> = self.cookies.lock().unwrap().iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .map(|cookie| cookie.to_json())
+            .collect();
+        let mut temporary = path.as_ref().as_os_str().to_owned();
+        temporary.push(format!(".{}.tmp", ::std::process::id()));
+        let temporary = PathBuf::from(temporary);
+        {
+            let mut file = try!(OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&temporary));
+            try!(file.write_all(Value::Array(cookies).to_string().as_bytes()));
+        }
+        fs::rename(temporary, path)
+    }
+
+    /// Takes in the `Set-Cookie` values of a response from `url`.
+    pub fn store<S: AsRef<str>>(&self, url: &Url, set_cookies: &[S]) {
+        let now = cache::now();
+        let mut cookies = self.cookies.lock().unwrap();
+        for set_cookie in set_cookies {
+            let cookie = match Cookie::parse(set_cookie.as_ref(), url) {
+                Some(cookie) => cookie,
+                None => {
+                    debug!("Ignoring cookie from {}: {}", url, set_cookie.as_ref());
+                    continue;
+                }
+            };
+            cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+            if !cookie.is_expired(now) {
+                cookies.push(cookie);
+            }
+        }
+    }
+
+    /// The `Cookie` header for a request to `url`, longest paths first.
+    pub fn header(&self, url: &Url) -> Option<String> {
+        let now = cache::now();
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+
+        let mut matching: Vec<&Cookie> = cookies.iter().filter(|cookie| cookie.matches(url)).collect();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        let pairs: Vec<String> = matching.iter().map(|cookie| format!("{}={}", cookie.name, cookie.value)).collect();
+        Some(pairs.join("; "))
+    }
+
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.cookies.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+}
+
+fn is_ip(host: &str) -> bool {
+    match Host::parse(host) {
+        Ok(Host::Domain(_)) => false,
+        Ok(_) => true,
+        Err(_) => false
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.') && !is_ip(host))
+}
+
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The directory of the request path, e.g. `/a/b` -> `/a`.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => path[..index].to_string()
+    }
+}
+
+/// `Expires` comes in the RFC 1123 format and a few older ones.
+fn cookie_date(value: &str) -> Option<i64> {
+    cache::http_date(value).or_else(|| {
+        ["%A, %d-%b-%y %H:%M:%S GMT", "%a, %d-%b-%Y %H:%M:%S GMT", "%a %b %d %H:%M:%S %Y"].iter()
+            .filter_map(|format| time::strptime(value.trim(), format).ok())
+            .map(|tm| tm.to_timespec().sec)
+            .next()
+    })
+}
+
+#[test]
+fn cookie_parse() {
+    let url = Url::parse("https://admin.example.com/app/login").unwrap();
+
+    let cookie = Cookie::parse("sid=abc; Domain=.example.com; Path=/; Secure; HttpOnly; Max-Age=3600", &url).unwrap();
+    assert_eq!(cookie.domain, "example.com");
+    assert!(!cookie.host_only && cookie.secure && cookie.http_only);
+    assert!(cookie.matches(&Url::parse("https://api.example.com/x").unwrap()));
+    assert!(!cookie.matches(&Url::parse("http://api.example.com/x").unwrap()));
+
+    let cookie = Cookie::parse("theme=dark", &url).unwrap();
+    assert_eq!(cookie.path, "/app");
+    assert!(cookie.host_only);
+    assert!(!cookie.matches(&Url::parse("https://api.example.com/app").unwrap()));
+    assert!(!cookie.matches(&Url::parse("https://admin.example.com/application").unwrap()));
+
+    assert_eq!(Cookie::parse("sid=abc; Domain=other.com", &url), None);
+    assert_eq!(Cookie::parse("sid=abc; Domain=com", &Url::parse("https://example.com/").unwrap()), None);
+    let cookie = Cookie::parse("sid=abc; Domain=localhost", &Url::parse("http://localhost/").unwrap()).unwrap();
+    assert!(cookie.host_only);
+}
+
+#[test]
+fn cookie_jar_header() {
+    let url = Url::parse("http://example.com/app/page").unwrap();
+    let jar = CookieJar::new();
+    jar.store(&url, &["a=1; Path=/", "b=2; Path=/app", "c=3; Expires=Thu, 01 Jan 1970 00:00:00 GMT"]);
+
+    assert_eq!(jar.header(&url), Some("b=2; a=1".to_string()));
+
+    // Max-Age=0 deletes
+    jar.store(&url, &["b=gone; Path=/app; Max-Age=0"]);
+    assert_eq!(jar.header(&url), Some("a=1".to_string()));
+}
+
+#[test]
+fn cookie_jar_save() {
+    use std::env;
+    use std::os::unix::fs::PermissionsExt;
+
+    let url = Url::parse("http://example.com/").unwrap();
+    let jar = CookieJar::new();
+    jar.store(&url, &["sid=abc; HttpOnly"]);
+
+    let path = env::temp_dir().join(format!("rest-cookies-{}.json", time::precise_time_ns()));
+    jar.save(&path).unwrap();
+    let loaded = CookieJar::load(&path).unwrap();
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(mode & 0o777, 0o600);
+    assert_eq!(loaded.cookies(), jar.cookies());
+}
@@ -0,0 +1,109 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use url::Url;
+
+use proxy::{Proxies, ProxyKind};
+use resolve::{self, Resolver};
+
+/// What hyper's connection pool tells connections apart by.
+pub type Key = (&'static str, String, u16);
+
+/// Sets up connections on the thread that sends the request, before hyper's
+/// event loop asks for them, so the loop only picks up a socket and never waits
+/// for a lookup, a slow connect or a proxy handshake.
+pub struct Dialer {
+    resolver: Arc<Resolver>,
+    proxies: Proxies,
+    timeout: Duration,
+    ready: Mutex<HashMap<Key, VecDeque<io::Result<TcpStream>>>>
+}
+
+impl Dialer {
+    pub fn new(resolver: Arc<Resolver>, proxies: Proxies, timeout: Duration) -> Dialer {
+        Dialer {
+            resolver: resolver,
+            proxies: proxies,
+            timeout: timeout,
+            ready: Mutex::new(HashMap::new())
+        }
+    }
+
+    /// Connects for one request to `url` and keeps the stream, or why there is
+    /// none, until the connector takes it.
+    pub fn dial(&self, url: &Url) {
+        let key = match key(url) {
+            Some(key) => key,
+            None => return
+        };
+        let stream = self.connect(url, &key);
+        self.ready.lock().unwrap().entry(key).or_insert_with(VecDeque::new).push_back(stream);
+    }
+
+    fn connect(&self, url: &Url, key: &Key) -> io::Result<TcpStream> {
+        let proxy = match self.proxies.for_url(url) {
+            Some(proxy) => proxy,
+            None => return resolve::connect(&*self.resolver, &key.1, key.2, self.timeout)
+        };
+        debug!("Tunnelling to {}:{} through {}:{}", key.1, key.2, proxy.host, proxy.port);
+        // plain HTTP through an HTTP proxy only connects to the proxy, the request
+        // itself then names the absolute URL
+        if proxy.kind == ProxyKind::Http && url.scheme() == "http" {
+            proxy.dial(&*self.resolver, self.timeout)
+        } else {
+            proxy.tunnel(&*self.resolver, &key.1, key.2, self.timeout)
+        }
+    }
+
+    /// The stream dialled for the oldest waiting request to `key`.
+    pub fn take(&self, key: &Key) -> io::Result<TcpStream> {
+        let mut ready = self.ready.lock().unwrap();
+        let stream = ready.get_mut(key).and_then(|streams| streams.pop_front());
+        if ready.get(key).map_or(false, |streams| streams.is_empty()) {
+            ready.remove(key);
+        }
+        stream.unwrap_or(Err(io::Error::new(io::ErrorKind::NotConnected, "no connection was dialled for this request")))
+    }
+
+    /// Drops the stream dialled for a request that never reached the event loop.
+    pub fn discard(&self, url: &Url) {
+        if let Some(key) = key(url) {
+            let _ = self.take(&key);
+        }
+    }
+}
+
+pub fn key(url: &Url) -> Option<Key> {
+    let scheme = match url.scheme() {
+        "http" => "http",
+        "https" => "https",
+        _ => return None
+    };
+    Some((scheme, try_opt!(url.host_str()).to_string(), try_opt!(url.port_or_known_default())))
+}
+
+#[test]
+fn dialer_hands_out_in_order() {
+    use std::net::TcpListener;
+    use proxy::Proxy;
+    use resolve::SystemResolver;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut proxies = Proxies::new();
+    proxies.http = Proxy::parse(&listener.local_addr().unwrap().to_string());
+    let dialer = Dialer::new(Arc::new(SystemResolver), proxies, Duration::from_secs(5));
+    let url = Url::parse("http://example.com/posts").unwrap();
+    let key = key(&url).unwrap();
+
+    dialer.dial(&url);
+    dialer.dial(&url);
+    let first = dialer.take(&key).unwrap();
+    let (accepted, _) = listener.accept().unwrap();
+    assert_eq!(first.local_addr().unwrap(), accepted.peer_addr().unwrap());
+
+    dialer.discard(&url);
+    assert_eq!(dialer.take(&key).unwrap_err().kind(), io::ErrorKind::NotConnected);
+}
@@ -23,5 +23,77 @@ quick_error! {
         cause(err)
         from()
         }
+    /// Wrapper for a `::std::string::FromUtf8Error`
+        Utf8Error(err: ::std::string::FromUtf8Error) {
+        description("UTF-8 error")
+        display("UTF-8 error: {}", err)
+        cause(err)
+        from()
+        }
+    /// The server answered with an `application/problem+json` error
+        Problem(problem: ::problem::Problem) {
+        description("problem details")
+        display("problem: {}", problem)
+        }
+    /// The server answered with an error status and no problem details
+        Status(status: u16, body: String) {
+        description("error status")
+        display("server returned {}: {}", status, body)
+        }
+    /// An `If-Match` update was asked for but the resource has no `ETag`
+        MissingETag {
+        description("missing ETag")
+        display("the resource did not return an ETag")
+        }
+    /// Every `If-Match` attempt was rejected with 412 Precondition Failed
+        PreconditionFailed(attempts: usize) {
+        description("precondition failed")
+        display("precondition failed after {} attempts", attempts)
+        }
+    /// The redirect policy's hop limit was reached
+        TooManyRedirects(hops: usize) {
+        description("too many redirects")
+        display("too many redirects: stopped after {} hops", hops)
+        }
+    /// A codec failed to encode or decode a body
+        Codec(err: String) {
+        description("codec error")
+        display("codec error: {}", err)
+        }
+    /// No registered codec handles the response media type
+        UnsupportedContentType(media_type: String) {
+        description("unsupported content type")
+        display("no codec registered for {}", media_type)
+        }
+    /// The endpoint's circuit breaker is open and failing requests fast
+        CircuitOpen {
+        description("circuit open")
+        display("circuit breaker is open")
+        }
+    /// The endpoint's concurrency limit and queue were full, or the queue timeout ran out
+        BulkheadFull(in_flight: usize, queued: usize) {
+        description("bulkhead full")
+        display("too many concurrent requests: {} in flight, {} queued", in_flight, queued)
+        }
+    /// A TLS certificate, key or setting could not be loaded
+        Tls(err: String) {
+        description("TLS error")
+        display("TLS error: {}", err)
+        }
+    /// A proxy url that is not an `http://` or `socks5://` one
+        InvalidProxy(url: String) {
+        description("invalid proxy")
+        display("invalid proxy url {}, only http:// and socks5:// proxies are supported", url)
+        }
+    /// A request body read from a one-off reader would have to be sent again
+        BodyNotReplayable {
+        description("body not replayable")
+        display("the request body was read from a reader and cannot be sent again")
+        }
+    /// The (decoded) response body grew past the endpoint's size limit
+        BodyTooLarge(limit: usize) {
+        description("response body too large")
+        display("response body exceeds the {} byte limit", limit)
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Latencies remembered for `HedgePolicy::Percentile`.
+const SAMPLES: usize = 100;
+/// Below this many samples the percentile policy uses its fallback delay.
+const MIN_SAMPLES: usize = 10;
+
+/// When to send a duplicate of a slow GET, see `EndpointBuilder::hedge`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HedgePolicy
+{
+    /// After a fixed delay.
+    After(Duration),
+    /// Once the attempt is slower than this percentile (0.0 to 1.0) of recent
+    /// responses, using the fallback delay until enough have been seen.
+    Percentile(f64, Duration)
+}
+
+pub struct Hedging {
+    policy: HedgePolicy,
+    pub max_attempts: usize,
+    samples: Mutex<VecDeque<Duration>>
+}
+
+impl Hedging {
+    pub fn new(policy: HedgePolicy, max_attempts: usize) -> Hedging {
+        Hedging {
+            policy: policy,
+            max_attempts: ::std::cmp::max(max_attempts, 1),
+            samples: Mutex::new(VecDeque::new())
+        }
+    }
+
+    /// How long to give an attempt before hedging it.
+    pub fn delay(&self) -> Duration {
+        match self.policy {
+            HedgePolicy::After(delay) => delay,
+            HedgePolicy::Percentile(percentile, fallback) => {
+                let samples = self.samples.lock().unwrap();
+                if samples.len() < MIN_SAMPLES {
+                    return fallback;
+                }
+                let mut sorted: Vec<Duration> = samples.iter().cloned().collect();
+                sorted.sort();
+                let rank = (percentile.max(0.0).min(1.0) * (sorted.len() - 1) as f64).round() as usize;
+                sorted[rank]
+            }
+        }
+    }
+
+    /// Remembers how long the first attempt of a request took, or had been
+    /// running when a hedge answered first.
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.push_back(latency);
+        while samples.len() > SAMPLES {
+            samples.pop_front();
+        }
+    }
+}
+
+#[test]
+fn hedging_percentile() {
+    let hedging = Hedging::new(HedgePolicy::Percentile(0.9, Duration::from_millis(50)), 2);
+    assert_eq!(hedging.delay(), Duration::from_millis(50));
+
+    for ms in 1..21 {
+        hedging.record(Duration::from_millis(ms * 10));
+    }
+    assert_eq!(hedging.delay(), Duration::from_millis(180));
+}
@@ -0,0 +1,138 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rand;
+use url::Url;
+
+/// How an endpoint with several base URLs spreads requests over them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Balancing
+{
+    RoundRobin,
+    Random,
+    /// Random, in proportion to the weights given to `EndpointBuilder::add_url`.
+    Weighted
+}
+
+/// The base URLs of an endpoint and which of them are currently marked down.
+pub struct Hosts {
+    hosts: Vec<Host>,
+    balancing: Balancing,
+    cooldown: Duration,
+    next: AtomicUsize
+}
+
+struct Host {
+    url: Url,
+    weight: u32,
+    down_until: Mutex<Option<Instant>>
+}
+
+impl Hosts {
+    /// `urls` pairs each base URL with its weight; it must not be empty.
+    pub fn new(urls: Vec<(Url, u32)>, balancing: Balancing, cooldown: Duration) -> Hosts {
+        Hosts {
+            hosts: urls.into_iter().map(|(url, weight)| Host {
+                url: url,
+                weight: weight,
+                down_until: Mutex::new(None)
+            }).collect(),
+            balancing: balancing,
+            cooldown: cooldown,
+            next: AtomicUsize::new(0)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// The first base URL, used for anything that has to be stable across hosts like cache keys.
+    pub fn primary(&self) -> &Url {
+        &self.hosts[0].url
+    }
+
+    pub fn url(&self, host: usize) -> &Url {
+        &self.hosts[host].url
+    }
+
+    /// Picks a host that is up and not in `tried`. When every host is down the
+    /// untried ones are all fair game again, as failing fast helps nobody.
+    pub fn pick(&self, tried: &[usize]) -> Option<usize> {
+        let now = Instant::now();
+        let untried: Vec<usize> = (0..self.hosts.len()).filter(|host| !tried.contains(host)).collect();
+        let up: Vec<usize> = untried.iter().cloned().filter(|&host| self.is_up(host, now)).collect();
+        let candidates = if up.is_empty() { untried } else { up };
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let index = match self.balancing {
+            Balancing::RoundRobin => self.next.fetch_add(1, Ordering::SeqCst) % candidates.len(),
+            Balancing::Random => rand::random::<usize>() % candidates.len(),
+            Balancing::Weighted => {
+                let total: u64 = candidates.iter().map(|&host| self.hosts[host].weight as u64).sum();
+                if total == 0 {
+                    rand::random::<usize>() % candidates.len()
+                } else {
+                    let mut point = rand::random::<u64>() % total;
+                    candidates.iter()
+                        .position(|&host| {
+                            let weight = self.hosts[host].weight as u64;
+                            if point < weight { true } else { point -= weight; false }
+                        })
+                        .unwrap_or(0)
+                }
+            }
+        };
+        Some(candidates[index])
+    }
+
+    /// Takes `host` out of rotation for the cooldown.
+    pub fn mark_down(&self, host: usize) {
+        warn!("Marking {} down for {:?}", self.hosts[host].url, self.cooldown);
+        *self.hosts[host].down_until.lock().unwrap() = Some(Instant::now() + self.cooldown);
+    }
+
+    pub fn mark_up(&self, host: usize) {
+        *self.hosts[host].down_until.lock().unwrap() = None;
+    }
+
+    fn is_up(&self, host: usize, now: Instant) -> bool {
+        self.hosts[host].down_until.lock().unwrap().map_or(true, |until| until <= now)
+    }
+}
+
+#[test]
+fn hosts_round_robin() {
+    let hosts = Hosts::new(vec![
+        (Url::parse("http://a.example.com/").unwrap(), 1),
+        (Url::parse("http://b.example.com/").unwrap(), 1),
+        (Url::parse("http://c.example.com/").unwrap(), 1)
+    ], Balancing::RoundRobin, Duration::from_secs(60));
+
+    assert_eq!(hosts.pick(&[]), Some(0));
+    assert_eq!(hosts.pick(&[]), Some(1));
+
+    hosts.mark_down(2);
+    assert_eq!(hosts.pick(&[]), Some(0));
+    assert_eq!(hosts.pick(&[0]), Some(1));
+    assert_eq!(hosts.pick(&[0, 1]), Some(2));
+    assert_eq!(hosts.pick(&[0, 1, 2]), None);
+
+    hosts.mark_up(2);
+    assert_eq!(hosts.pick(&[0, 1]), Some(2));
+}
+
+#[test]
+fn hosts_weighted() {
+    let hosts = Hosts::new(vec![
+        (Url::parse("http://a.example.com/").unwrap(), 0),
+        (Url::parse("http://b.example.com/").unwrap(), 5)
+    ], Balancing::Weighted, Duration::from_secs(60));
+
+    for _ in 0..20 {
+        assert_eq!(hosts.pick(&[]), Some(1));
+    }
+}
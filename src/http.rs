@@ -1,17 +1,52 @@
-use std::io;
-use std::sync::mpsc;
-use std::time::Duration;
-use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 
-use hyper::client::{Request as HyperRequest, Response as HyperResponse, DefaultTransport as HttpStream};
-use hyper::header::{Connection, Headers, UserAgent};
+use hyper::client::{Request as HyperRequest, Response as HyperResponse};
+use hyper::header::{AcceptEncoding, Connection, ContentLength, Headers, Location, TransferEncoding, UserAgent};
+use hyper::header::Encoding as TransferCoding;
+use hyper::net::Transport;
 use hyper::{Decoder, Encoder, Next};
 use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
 use hyper;
 
+use rand;
 use url::Url;
 
-pub type ResultSender = mpsc::Sender<(Request, Option<Response>)>;
+use breaker::{self, CircuitBreaker};
+use bulkhead::{Bulkhead, Permit};
+use cache::{self, CacheEntry, CacheStore, Freshness};
+use codec::{Codecs, Payload};
+use compression::{self, BodyDecoder, Encoding};
+use cookie::CookieJar;
+use dial::Dialer;
+use error::Error;
+use hedge::Hedging;
+use hosts::{Balancing, Hosts};
+use proxy::{ProxyKind, Proxies};
+use ratelimit::RateLimiter;
+use redirect::{self, RedirectPolicy};
+use resolve::{Resolver, ResolvingConnector, SystemResolver};
+use tls::Tls;
+use unix::UnixConnector;
+
+pub type ResultSender = mpsc::Sender<(Request, Result<Response, Error>)>;
+pub type ChunkSender = mpsc::SyncSender<Result<Vec<u8>, Error>>;
+pub type ChunkReceiver = mpsc::Receiver<Result<Vec<u8>, Error>>;
+
+pub const IDEMPOTENCY_KEY: &'static str = "Idempotency-Key";
+
+/// Decoded chunks queued for a streaming reader before the handler blocks.
+const STREAM_BUFFER: usize = 16;
+
+/// Bytes pulled from a request body source per write.
+const UPLOAD_CHUNK: usize = 8192;
 
 #[derive(Debug, Clone)]
 pub enum Method
@@ -30,21 +65,94 @@ pub enum Method
 pub struct Response {
     pub status: StatusCode,
     pub headers: Headers,
-    pub body: Option<Vec<u8>>
+    pub body: Option<Vec<u8>>,
+    pub url: Url,
+    pub redirects: Vec<Url>
 }
 
 pub struct Handler {
     request: Request,
+    url: Url,
+    headers: HashMap<String, String>,
     response: Option<Response>,
+    decompress: bool,
+    max_body_size: Option<usize>,
+    body: Option<BodyDecoder<BodySink>>,
+    upload: Option<Upload>,
+    stream: Option<ChunkSender>,
+    head_sent: bool,
+    /// Asks for the whole URL, as an HTTP proxy forwarding the request needs.
+    absolute_form: bool,
     sender: ResultSender,
     user_agent: String,
 }
 
+/// Where the handler puts decoded response bytes.
+pub enum BodySink {
+    Buffer(Vec<u8>),
+    Stream(ChunkSender)
+}
+
+/// Opens a fresh reader over a request body.
+pub type Source = Arc<Fn() -> io::Result<Box<Read + Send>> + Send + Sync>;
+
+/// A request body, either in memory or pulled from a reader while sending.
+#[derive(Clone)]
+pub enum RequestBody {
+    Bytes(Vec<u8>),
+    /// A reader that can be sent only once. Redirects, failover and retries
+    /// that would resend it fail with `Error::BodyNotReplayable` instead.
+    Reader(Arc<Mutex<Option<Box<Read + Send>>>>, Option<u64>),
+    /// Reopened for every send, so it can be replayed in full.
+    Source(Source, Option<u64>)
+}
+
+/// Write side state of the request body in flight.
+struct Upload {
+    source: Box<Read + Send>,
+    buf: Vec<u8>,
+    pos: usize,
+    len: usize
+}
+
+/// What a response or failure does to the endpoint's rate limits, breaker and cookies.
+enum Outcome {
+    Response(Url, StatusCode, Headers),
+    /// Whether the error counts against the circuit breaker.
+    Failed(bool)
+}
+
+/// The outcomes of one hedged attempt, held back until it is known to have won.
+/// Dropped without being applied, they only give back the breaker slots they held.
+struct Outcomes {
+    list: Arc<Mutex<Vec<Outcome>>>,
+    breaker: Option<Arc<CircuitBreaker>>
+}
+
+/// A hedged attempt that is still running, see `Client::hedged`.
+struct Hedge {
+    id: usize,
+    client: hyper::Client<Handler>,
+    /// Set by whichever comes first: the attempt finishing or it being cancelled.
+    settled: Arc<Mutex<bool>>
+}
+
+/// A response body still being read by the event loop.
+pub struct Body {
+    // dropped first, so the handler stops writing before its event loop is closed
+    chunks: ChunkReceiver,
+    endpoint: Option<Endpoint>,
+    permit: Option<Permit>
+}
+
 #[derive(Debug, Clone)]
 pub struct Request {
      method: hyper::Method,
      route: Option<String>,
-     body: Option<String>
+     body: Option<RequestBody>,
+     value: Option<Payload>,
+     compression: Option<(Encoding, usize)>,
+     headers: HashMap<String, String>
 }
 
 pub struct Header {
@@ -93,13 +201,170 @@ impl Request {
         Request {
             method: method.to_hyper(),
             route: route,
-            body: body
+            body: body.map(|body| RequestBody::Bytes(body.into_bytes())),
+            value: None,
+            compression: None,
+            headers: HashMap::new()
         }
     }
 
     pub fn method(&self) -> Method {
         Method::from_hyper(&self.method)
     }
+
+    pub fn route(&self) -> Option<&String> {
+        self.route.as_ref()
+    }
+
+    pub fn set_route(&mut self, route: String) {
+        self.route = Some(route);
+    }
+
+    pub fn set_body(&mut self, body: RequestBody) {
+        self.body = Some(body);
+    }
+
+    /// Sets a body that is encoded with the endpoint's codec when the request is sent.
+    pub fn set_value(&mut self, value: Payload) {
+        self.value = Some(value);
+    }
+
+    fn encode_value(&mut self, codecs: &Codecs) -> Result<(), Error> {
+        let value = match self.value.take() {
+            Some(value) => value,
+            None => return Ok(())
+        };
+        let codec = codecs.primary();
+        self.body = Some(RequestBody::Bytes(try!(value.encode(codec))));
+        self.set_header("Content-Type", codec.content_type());
+        if let Some((encoding, threshold)) = self.compression {
+            self.compress(encoding, threshold);
+        }
+        Ok(())
+    }
+
+    pub fn body_len(&self) -> Option<u64> {
+        self.body.as_ref().and_then(|body| body.len())
+    }
+
+    pub fn header(&self, name: &str) -> Option<&String> {
+        self.headers.get(name)
+    }
+
+    pub fn headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn idempotency_key(&self) -> Option<&String> {
+        self.headers.get(IDEMPOTENCY_KEY)
+    }
+
+    /// Compresses the body with `encoding` if it is at least `threshold` bytes long.
+    pub fn compress(&mut self, encoding: Encoding, threshold: usize) {
+        self.compression = Some((encoding, threshold));
+        let compressed = match self.body {
+            Some(RequestBody::Bytes(ref body)) if body.len() >= threshold && encoding != Encoding::Identity => {
+                compression::encode(encoding, body)
+            },
+            _ => return
+        };
+        match compressed {
+            Ok(compressed) => {
+                self.body = Some(RequestBody::Bytes(compressed));
+                self.set_header("Content-Encoding", encoding.name());
+            },
+            Err(e) => warn!("Could not {} request body, sending it uncompressed: {}", encoding.name(), e)
+        }
+    }
+}
+
+impl RequestBody {
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, len: Option<u64>) -> RequestBody {
+        RequestBody::Reader(Arc::new(Mutex::new(Some(Box::new(reader)))), len)
+    }
+
+    pub fn from_source<F>(open: F, len: Option<u64>) -> RequestBody
+        where F: Fn() -> io::Result<Box<Read + Send>> + Send + Sync + 'static
+    {
+        RequestBody::Source(Arc::new(open), len)
+    }
+
+    /// The body length, if it is known up front.
+    pub fn len(&self) -> Option<u64> {
+        match *self {
+            RequestBody::Bytes(ref bytes) => Some(bytes.len() as u64),
+            RequestBody::Reader(_, len) | RequestBody::Source(_, len) => len
+        }
+    }
+
+    /// Whether the body can be sent more than once.
+    pub fn replayable(&self) -> bool {
+        match *self {
+            RequestBody::Reader(..) => false,
+            _ => true
+        }
+    }
+
+    /// A reader over the whole body. A `Reader` body hands out its reader once.
+    pub fn open(&self) -> Result<Box<Read + Send>, Error> {
+        match *self {
+            RequestBody::Bytes(ref bytes) => Ok(Box::new(io::Cursor::new(bytes.clone()))),
+            RequestBody::Reader(ref reader, _) => match reader.lock() {
+                Ok(mut reader) => reader.take().ok_or(Error::BodyNotReplayable),
+                Err(_) => Err(Error::BodyNotReplayable)
+            },
+            RequestBody::Source(ref open, _) => Ok(try!(open()))
+        }
+    }
+}
+
+impl fmt::Debug for RequestBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RequestBody::Bytes(ref bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            RequestBody::Reader(_, Some(len)) => write!(f, "Reader({} bytes)", len),
+            RequestBody::Reader(_, None) => write!(f, "Reader(chunked)"),
+            RequestBody::Source(_, Some(len)) => write!(f, "Source({} bytes)", len),
+            RequestBody::Source(_, None) => write!(f, "Source(chunked)")
+        }
+    }
+}
+
+impl Upload {
+    fn new(source: Box<Read + Send>) -> Upload {
+        Upload {
+            source: source,
+            buf: vec![0; UPLOAD_CHUNK],
+            pos: 0,
+            len: 0
+        }
+    }
+
+    fn pending(&self) -> &[u8] {
+        &self.buf[self.pos..self.len]
+    }
+
+    /// Refills the buffer once everything pending has been written, returns 0 at the end of the body.
+    fn fill(&mut self) -> io::Result<usize> {
+        if self.pos < self.len {
+            return Ok(self.len - self.pos);
+        }
+        loop {
+            match self.source.read(&mut self.buf) {
+                Ok(n) => {
+                    self.pos = 0;
+                    self.len = n;
+                    return Ok(n);
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        }
+    }
 }
 
 impl Handler {
@@ -113,58 +378,519 @@ impl Handler {
     }
 
     fn send_result(&self) {
-        self.sender.send((self.request.clone(), self.response.clone())).unwrap();
+        let response = self.response.clone().ok_or(Error::from(hyper::Error::Incomplete));
+        if self.sender.send((self.request.clone(), response)).is_err() {
+            debug!("Nobody is waiting for the response from {} any more", self.url);
+        }
+    }
+
+    /// Reports `err` to whoever is waiting: the caller before the head was
+    /// handed over, the body reader after.
+    fn fail(&mut self, err: Error) {
+        if self.head_sent {
+            if let Some(stream) = self.stream.take() {
+                let _ = stream.send(Err(err));
+            }
+        } else {
+            let _ = self.sender.send((self.request.clone(), Err(err)));
+        }
+    }
+
+    fn content_encoding(headers: &Headers) -> Encoding {
+        headers.get_raw("Content-Encoding")
+            .and_then(|values| values.first())
+            .and_then(|value| Encoding::from_header(&String::from_utf8_lossy(value)))
+            .unwrap_or(Encoding::Identity)
+    }
+
+    fn read_body(&mut self, encoding: Encoding) -> Next {
+        let sink = match self.stream {
+            Some(ref stream) => BodySink::Stream(stream.clone()),
+            None => BodySink::Buffer(Vec::new())
+        };
+        self.body = Some(BodyDecoder::new(encoding, sink, self.max_body_size));
+        if self.stream.is_some() {
+            self.send_result();
+            self.head_sent = true;
+        }
+        self.read()
+    }
+
+    fn finish_body(&mut self) -> Next {
+        let body = match self.body.take() {
+            Some(body) => body,
+            None => return self.return_response()
+        };
+        match body.finish() {
+            Ok(BodySink::Buffer(bytes)) => {
+                if let Some(ref mut response) = self.response {
+                    response.body = Some(bytes);
+                }
+                self.return_response()
+            },
+            Ok(BodySink::Stream(_)) => {
+                self.stream = None;
+                Next::end()
+            },
+            Err(e) => {
+                self.fail(Error::from(e));
+                Next::end()
+            }
+        }
     }
 }
 
+impl Write for BodySink {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match *self {
+            BodySink::Buffer(ref mut buf) => buf.write(data),
+            BodySink::Stream(ref sender) => {
+                //blocks the event loop until the reader catches up
+                match sender.send(Ok(data.to_vec())) {
+                    Ok(()) => Ok(data.len()),
+                    Err(_) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "response stream was dropped"))
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Iterator for Body {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>, Error>> {
+        self.chunks.recv().ok()
+    }
+}
+
+/// A random (version 4) UUID for the `Idempotency-Key` header.
+pub fn idempotency_key() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", hex[0..4].concat(), hex[4..6].concat(), hex[6..8].concat(), hex[8..10].concat(), hex[10..16].concat())
+}
+
 pub struct Client;
 
 pub struct Endpoint
 {
-    url: Url,
+    pub hosts: Arc<Hosts>,
+    timeout: Duration,
     client: hyper::Client<Handler>,
-    header: HashMap<String, String>
+    /// Sets up the connections that the client's connector only picks up.
+    dialer: Option<Arc<Dialer>>,
+    header: HashMap<String, String>,
+    pub redirect: RedirectPolicy,
+    pub decompress: bool,
+    pub max_body_size: Option<usize>,
+    pub codecs: Codecs,
+    pub cache: Option<Arc<CacheStore>>,
+    pub limiter: Arc<RateLimiter>,
+    pub breaker: Option<Arc<CircuitBreaker>>,
+    pub bulkhead: Option<Arc<Bulkhead>>,
+    pub hedging: Option<Arc<Hedging>>,
+    pub cookies: Option<Arc<CookieJar>>,
+    /// Cache keys with a background revalidation in flight.
+    revalidating: Arc<Mutex<HashSet<String>>>,
+    /// Idle forks that hedged attempts run on, so a hedge does not start a new event loop.
+    hedgers: Arc<Mutex<Vec<Endpoint>>>,
+    /// Set on hedged attempts, whose outcomes only count if they win.
+    outcomes: Option<Arc<Mutex<Vec<Outcome>>>>,
+    tls: Option<Tls>,
+    proxies: Option<Proxies>,
+    unix_socket: Option<PathBuf>,
+    resolver: Option<Arc<Resolver>>
 }
 
 impl Endpoint {
     pub fn new(url: String, timeout: Duration, header: HashMap<String,String>) -> Endpoint {
+        let (client, dialer) = Endpoint::connector(timeout, None, None, None, None);
+        Endpoint {
+            hosts: Arc::new(Hosts::new(vec![(Endpoint::url(&url), 1)], Balancing::RoundRobin, Duration::from_secs(0))),
+            timeout: timeout,
+            client: client,
+            dialer: dialer,
+            header: header,
+            redirect: RedirectPolicy::None,
+            decompress: true,
+            max_body_size: None,
+            codecs: Codecs::new(),
+            cache: None,
+            limiter: Arc::new(RateLimiter::new(None)),
+            breaker: None,
+            bulkhead: None,
+            hedging: None,
+            cookies: None,
+            revalidating: Arc::new(Mutex::new(HashSet::new())),
+            hedgers: Arc::new(Mutex::new(Vec::new())),
+            outcomes: None,
+            tls: None,
+            proxies: None,
+            unix_socket: None,
+            resolver: None
+        }
+    }
+
+    /// A copy of this endpoint with its own connection pool, for background work.
+    /// The pool's event loop shuts down when the copy is dropped.
+    fn fork(&self) -> Endpoint {
+        let (client, dialer) = Endpoint::connector(self.timeout, self.tls.as_ref(), self.proxies.as_ref(), self.unix_socket.as_ref(), self.resolver.as_ref());
         Endpoint {
-            url: Endpoint::url(&url),
-            client: Endpoint::connector(timeout),
-            header: header
+            hosts: self.hosts.clone(),
+            timeout: self.timeout,
+            client: client,
+            dialer: dialer,
+            header: self.header.clone(),
+            redirect: self.redirect.clone(),
+            decompress: self.decompress,
+            max_body_size: self.max_body_size,
+            codecs: self.codecs.clone(),
+            cache: self.cache.clone(),
+            limiter: self.limiter.clone(),
+            breaker: self.breaker.clone(),
+            bulkhead: self.bulkhead.clone(),
+            hedging: self.hedging.clone(),
+            cookies: self.cookies.clone(),
+            revalidating: self.revalidating.clone(),
+            // a fork sharing the pool it sits in would keep itself alive
+            hedgers: Arc::new(Mutex::new(Vec::new())),
+            outcomes: None,
+            tls: self.tls.clone(),
+            proxies: self.proxies.clone(),
+            unix_socket: self.unix_socket.clone(),
+            resolver: self.resolver.clone()
         }
     }
+    /// Connects with `tls` instead of the default TLS settings.
+    pub fn set_tls(&mut self, tls: Tls) {
+        self.tls = Some(tls);
+        self.reconnect();
+    }
+
+    /// Tunnels requests through `proxies`.
+    pub fn set_proxies(&mut self, proxies: Proxies) {
+        self.proxies = Some(proxies);
+        self.reconnect();
+    }
+
+    /// Sends every request over the Unix domain socket at `path` instead of TCP.
+    pub fn set_unix_socket(&mut self, path: PathBuf) {
+        self.unix_socket = Some(path);
+        self.reconnect();
+    }
+
+    /// Looks up hosts with `resolver`, including proxies and the hosts they are bypassed for;
+    /// the targets of proxied requests are still resolved by the proxy.
+    pub fn set_resolver(&mut self, resolver: Arc<Resolver>) {
+        self.resolver = Some(resolver);
+        self.reconnect();
+    }
+
+    /// Swaps the event loop for one built from the current connection settings.
+    fn reconnect(&mut self) {
+        let (client, dialer) = Endpoint::connector(self.timeout, self.tls.as_ref(), self.proxies.as_ref(), self.unix_socket.as_ref(), self.resolver.as_ref());
+        mem::replace(&mut self.client, client).close();
+        self.dialer = dialer;
+        // forks made with the old settings are dropped, closing their event loops
+        self.hedgers = Arc::new(Mutex::new(Vec::new()));
+    }
+
+    /// Backs the endpoint with `urls` on top of its own, each with a weight.
+    pub fn add_hosts(&mut self, urls: &[(String, u32)], balancing: Balancing, cooldown: Duration) {
+        let mut hosts = vec![(self.hosts.primary().clone(), 1)];
+        hosts.extend(urls.iter().map(|&(ref url, weight)| (Endpoint::url(url), weight)));
+        self.hosts = Arc::new(Hosts::new(hosts, balancing, cooldown));
+    }
+
     fn url (url: &String) -> Url {
         Url::parse(url).unwrap()
     }
 
-    fn connector(timeout: Duration) -> hyper::Client<Handler> {
-        hyper::Client::<Handler>::configure()
+    /// The client for the given settings, with the dialer its connector relies on, if any.
+    fn connector(timeout: Duration, tls: Option<&Tls>, proxies: Option<&Proxies>, unix_socket: Option<&PathBuf>,
+                 resolver: Option<&Arc<Resolver>>) -> (hyper::Client<Handler>, Option<Arc<Dialer>>)
+    {
+        let config = hyper::Client::<Handler>::configure()
             .connect_timeout(timeout)
             .keep_alive(true)
-            .keep_alive_timeout(Some(timeout))
-            .build()
-            .unwrap()
+            .keep_alive_timeout(Some(timeout));
+        if let Some(path) = unix_socket {
+            return (config.connector(UnixConnector::new(path)).build().unwrap(), None);
+        }
+        let tls = tls.cloned().unwrap_or_default();
+        if proxies.is_none() && resolver.is_none() {
+            return (config.connector(tls.connector()).build().unwrap(), None);
+        }
+        let resolver = resolver.cloned().unwrap_or(Arc::new(SystemResolver));
+        let dialer = Arc::new(Dialer::new(resolver, proxies.cloned().unwrap_or(Proxies::new()), timeout));
+        (config.connector(ResolvingConnector::new(dialer.clone(), tls)).build().unwrap(), Some(dialer))
+    }
+}
+
+impl Outcomes {
+    fn apply(self, endpoint: &Endpoint) {
+        for outcome in self.list.lock().unwrap().drain(..) {
+            Client::settle(endpoint, &outcome);
+        }
+    }
+}
+
+impl Drop for Outcomes {
+    fn drop(&mut self) {
+        if let Some(ref breaker) = self.breaker {
+            for _ in self.list.lock().unwrap().drain(..) {
+                breaker.release();
+            }
+        }
+    }
+}
+
+impl Hedge {
+    /// Stops the attempt by closing its event loop, which drops its handler.
+    fn cancel(&self) {
+        let mut settled = self.settled.lock().unwrap();
+        if !*settled {
+            *settled = true;
+            self.client.clone().close();
+        }
+    }
+}
+
+/// The event loop is shared by every request on the endpoint and only stops with it.
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.client.clone().close();
     }
 }
 
 impl Client {
-    pub fn request(endpoint: &Endpoint, request: &Request) -> String
+    pub fn request(endpoint: &Endpoint, request: &Request) -> Result<Response, Error>
+    {
+        let _permit = try!(Client::permit(endpoint));
+        match endpoint.cache {
+            Some(ref cache) if request.method == hyper::Method::Get => Client::cached(endpoint, &**cache, request),
+            _ => Client::fetch(endpoint, request)
+        }
+    }
+
+    /// Sends `request`, hedged when the endpoint asks for it.
+    fn fetch(endpoint: &Endpoint, request: &Request) -> Result<Response, Error>
+    {
+        match endpoint.hedging {
+            Some(ref hedging) if request.method == hyper::Method::Get || request.method == hyper::Method::Head => {
+                Client::hedged(endpoint, &**hedging, request)
+            },
+            _ => Client::follow(endpoint, request, false).map(|(res, _)| res)
+        }
+    }
+
+    /// Sends `request` and, whenever no attempt has answered within the hedging
+    /// delay, a duplicate of it. The first response wins and only its effect on
+    /// rate limits, the breaker and cookies is kept; the other attempts are cancelled.
+    fn hedged(endpoint: &Endpoint, hedging: &Hedging, request: &Request) -> Result<Response, Error>
     {
-        let req = request.clone();
-        let client = endpoint.client.clone();
-        let mut url = endpoint.url.clone();
         let (tx, rx) = mpsc::channel();
+        let mut running: Vec<Hedge> = Vec::new();
+        let mut attempts = 0;
+        let mut failure: Option<(Error, Outcomes)> = None;
+        let started = Instant::now();
+        let mut primary_failed = false;
 
-        let handler = Handler {
-            request: req,
-            response: None,
-            sender: tx,
-            user_agent: "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_11_6) \
-            AppleWebKit/537.36 (KHTML, like Gecko) \
-            Chrome/52.0.2743.116 Safari/537.36".to_owned(),
+        loop {
+            if running.is_empty() {
+                if attempts >= hedging.max_attempts {
+                    return match failure {
+                        Some((err, outcomes)) => {
+                            outcomes.apply(endpoint);
+                            Err(err)
+                        },
+                        None => Err(Error::from(hyper::Error::Incomplete))
+                    };
+                }
+                running.push(Client::attempt(endpoint, request, attempts, None, tx.clone()));
+                attempts += 1;
+            }
+
+            let received = if attempts < hedging.max_attempts {
+                rx.recv_timeout(hedging.delay()).ok()
+            } else {
+                rx.recv().ok()
+            };
+
+            match received {
+                Some((id, Ok(res), outcomes)) => {
+                    // how long the first attempt took, or had taken when a hedge beat it,
+                    // so the delay is not learned from the fastest attempts alone
+                    if !primary_failed {
+                        hedging.record(started.elapsed());
+                    }
+                    for hedge in running.iter().filter(|hedge| hedge.id != id) {
+                        hedge.cancel();
+                    }
+                    outcomes.apply(endpoint);
+                    return Ok(res);
+                },
+                Some((id, Err(err), outcomes)) => {
+                    running.retain(|hedge| hedge.id != id);
+                    primary_failed = primary_failed || id == 0;
+                    failure = Some((err, outcomes));
+                },
+                // a hedge is an extra request in flight and needs a bulkhead slot of its own
+                None => match Client::permit(endpoint) {
+                    Ok(permit) => {
+                        debug!("No answer after {:?}, hedging attempt {}", hedging.delay(), attempts + 1);
+                        running.push(Client::attempt(endpoint, request, attempts, permit, tx.clone()));
+                        attempts += 1;
+                    },
+                    Err(e) => {
+                        debug!("Not hedging: {}", e);
+                        attempts = hedging.max_attempts;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs one hedged attempt on a spare connector, forking one only when none is idle.
+    /// The attempt holds on to `permit` until it is done, and hands its connector
+    /// back afterwards unless it was cancelled.
+    fn attempt(endpoint: &Endpoint, request: &Request, id: usize, permit: Option<Permit>,
+               tx: mpsc::Sender<(usize, Result<Response, Error>, Outcomes)>) -> Hedge
+    {
+        let spare = endpoint.hedgers.lock().unwrap().pop();
+        let mut attempt = spare.unwrap_or_else(|| endpoint.fork());
+        let list = Arc::new(Mutex::new(Vec::new()));
+        attempt.outcomes = Some(list.clone());
+        let hedge = Hedge {
+            id: id,
+            client: attempt.client.clone(),
+            settled: Arc::new(Mutex::new(false))
         };
+        let settled = hedge.settled.clone();
+        let hedgers = endpoint.hedgers.clone();
+        let request = request.clone();
+        thread::spawn(move || {
+            let _permit = permit;
+            let result = Client::follow(&attempt, &request, false).map(|(res, _)| res);
+            let outcomes = Outcomes {
+                list: list,
+                breaker: attempt.breaker.clone()
+            };
+            let _ = tx.send((id, result, outcomes));
+            let mut settled = settled.lock().unwrap();
+            if !*settled {
+                *settled = true;
+                hedgers.lock().unwrap().push(attempt);
+            }
+        });
+        hedge
+    }
+
+    fn permit(endpoint: &Endpoint) -> Result<Option<Permit>, Error>
+    {
+        match endpoint.bulkhead {
+            Some(ref bulkhead) => Bulkhead::acquire(bulkhead).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Serves GETs from the endpoint cache, revalidating stale entries and
+    /// entries requested with `Cache-Control: no-cache`.
+    fn cached(endpoint: &Endpoint, cache: &CacheStore, request: &Request) -> Result<Response, Error>
+    {
+        let url = try!(Client::url(endpoint, request));
+        let key = cache::key(&url);
+        let headers = Client::headers(endpoint, request);
+
+        let entry = match cache.get(&key) {
+            Some(entry) => if entry.matches(&headers) { Some(entry) } else { None },
+            None => None
+        };
+
+        let entry = match entry {
+            Some(entry) => entry,
+            None => return Client::store(endpoint, cache, request, &key, &headers)
+        };
+
+        let freshness = if cache::no_cache(&headers) { Freshness::Stale } else { entry.freshness(cache::now()) };
+        match freshness {
+            Freshness::Fresh => {
+                debug!("Cache hit for {}", url);
+                Ok(entry.to_response(&url))
+            },
+            Freshness::StaleWhileRevalidate => {
+                debug!("Serving stale {} while revalidating", url);
+                if endpoint.revalidating.lock().unwrap().insert(key.clone()) {
+                    let background = endpoint.fork();
+                    let request = request.clone();
+                    let stale = entry.clone();
+                    thread::spawn(move || {
+                        // background refreshes count against the bulkhead like any other request
+                        match Client::permit(&background) {
+                            Ok(_permit) => if let Some(ref cache) = background.cache {
+                                let headers = Client::headers(&background, &request);
+                                let _ = Client::revalidate(&background, &**cache, &request, &key, &headers, stale);
+                            },
+                            Err(e) => debug!("Not revalidating {}: {}", key, e)
+                        }
+                        background.revalidating.lock().unwrap().remove(&key);
+                    });
+                }
+                Ok(entry.to_response(&url))
+            },
+            Freshness::Stale => Client::revalidate(endpoint, cache, request, &key, &headers, entry)
+        }
+    }
+
+    fn revalidate(endpoint: &Endpoint, cache: &CacheStore, request: &Request, key: &str,
+                  headers: &HashMap<String, String>, mut entry: CacheEntry) -> Result<Response, Error>
+    {
+        let mut conditional = request.clone();
+        for (name, value) in entry.validators() {
+            conditional.set_header(name, &value);
+        }
+        let res = try!(Client::fetch(endpoint, &conditional));
+
+        if res.status == StatusCode::NotModified {
+            entry.refresh(&res);
+            let response = entry.to_response(&res.url);
+            cache.put(key, entry);
+            return Ok(response);
+        }
+        Client::keep(cache, key, headers, res)
+    }
+
+    fn store(endpoint: &Endpoint, cache: &CacheStore, request: &Request, key: &str,
+             headers: &HashMap<String, String>) -> Result<Response, Error>
+    {
+        let res = try!(Client::fetch(endpoint, request));
+        Client::keep(cache, key, headers, res)
+    }
+
+    fn keep(cache: &CacheStore, key: &str, headers: &HashMap<String, String>, res: Response) -> Result<Response, Error>
+    {
+        match CacheEntry::from_response(&res, headers) {
+            Some(entry) => cache.put(key, entry),
+            None => if res.status == StatusCode::Ok {
+                cache.remove(key);
+            }
+        }
+        Ok(res)
+    }
+
+    fn url(endpoint: &Endpoint, request: &Request) -> Result<Url, Error>
+    {
+        Client::url_on(endpoint.hosts.primary(), request)
+    }
 
+    fn url_on(base: &Url, request: &Request) -> Result<Url, Error>
+    {
         //fix this hack
         let route =
         match request.route.as_ref() {
@@ -172,110 +898,425 @@ impl Client {
             None => "".to_string()
         };
 
-        let temp = url.to_string() + &route;
-         url = Url::parse(&temp).unwrap();
+        if Client::absolute(request) {
+            return Ok(try!(Url::parse(&route)));
+        }
+
+        let temp = base.to_string() + &route;
+        Ok(try!(Url::parse(&temp)))
+    }
+
+    /// Absolute routes, e.g. from a `Link` header, bypass the endpoint url.
+    fn absolute(request: &Request) -> bool
+    {
+        request.route.as_ref().map_or(false, |route| route.starts_with("http://") || route.starts_with("https://"))
+    }
+
+    /// Endpoint headers overlaid with the request's own.
+    fn headers(endpoint: &Endpoint, request: &Request) -> HashMap<String, String>
+    {
+        let mut headers = endpoint.header.clone();
+        if !headers.keys().any(|key| key.to_lowercase() == "accept") {
+            headers.insert("Accept".to_string(), endpoint.codecs.accept());
+        }
+        headers.extend(request.headers.clone());
+        headers
+    }
+
+    /// Like `request`, but hands back the head as soon as it arrives and
+    /// the body as it is decoded.
+    /// A slow reader stalls the event loop its body is read on, so every stream
+    /// gets a connector of its own instead of the endpoint's shared one.
+    /// The body holds on to the bulkhead slot and that connector until it is dropped.
+    pub fn stream(endpoint: &Endpoint, request: &Request) -> Result<(Response, Body), Error>
+    {
+        let permit = try!(Client::permit(endpoint));
+        let own = endpoint.fork();
+        match try!(Client::follow(&own, request, true)) {
+            (res, Some(chunks)) => {
+                let body = Body {
+                    chunks: chunks,
+                    endpoint: Some(own),
+                    permit: permit
+                };
+                Ok((res, body))
+            },
+            (res, None) => {
+                let (_, chunks) = mpsc::sync_channel(0);
+                Ok((res, Body { chunks: chunks, endpoint: None, permit: None }))
+            }
+        }
+    }
+
+    fn follow(endpoint: &Endpoint, request: &Request, stream: bool) -> Result<(Response, Option<ChunkReceiver>), Error>
+    {
+        let mut req = request.clone();
+        try!(req.encode_value(&endpoint.codecs));
+        let mut headers = Client::headers(endpoint, &req);
+        // an absolute route elsewhere only gets the credentials set on the request itself
+        if !redirect::same_origin(endpoint.hosts.primary(), &try!(Client::url(endpoint, &req))) {
+            redirect::strip_auth_headers(&mut headers);
+            headers.extend(req.headers.clone());
+        }
+        let mut redirects = Vec::new();
+        let (mut res, mut chunks, mut url) = try!(Client::balanced(endpoint, &req, &headers, stream));
+
+        loop {
+            if !redirect::is_redirect(&res.status) {
+                res.redirects = redirects;
+                return Ok((res, chunks));
+            }
+
+            let location = match res.headers.get::<Location>() {
+                Some(location) => try!(url.join(&location.to_string())),
+                None => {
+                    res.redirects = redirects;
+                    return Ok((res, chunks));
+                }
+            };
+
+            if !endpoint.redirect.allows(&url, &location) {
+                res.redirects = redirects;
+                return Ok((res, chunks));
+            }
+
+            if redirects.len() >= endpoint.redirect.max_hops() {
+                return Err(Error::TooManyRedirects(redirects.len()));
+            }
+
+            debug!("Following {} from {} to {}", res.status, url, location);
+
+            if !redirect::same_origin(&url, &location) {
+                redirect::strip_auth_headers(&mut headers);
+            }
+
+            let (method, keep_body) = redirect::next_method(&res.status, &req.method);
+            req.method = method;
+            if !keep_body {
+                req.body = None;
+                redirect::strip_body_headers(&mut req.headers);
+                redirect::strip_body_headers(&mut headers);
+            }
+
+            redirects.push(url);
+            url = location;
+
+            let (next, next_chunks) = try!(Client::send(endpoint, &req, &url, &headers, stream));
+            res = next;
+            chunks = next_chunks;
+        }
+    }
+
+    /// Sends to one of the endpoint's base URLs. Hosts that cannot be reached are
+    /// marked down and idempotent requests fail over to the next one.
+    /// Absolute routes go where they point, without touching the hosts' health.
+    fn balanced(endpoint: &Endpoint, req: &Request, headers: &HashMap<String, String>, stream: bool) -> Result<(Response, Option<ChunkReceiver>, Url), Error>
+    {
+        if let Some(key) = req.idempotency_key() {
+            debug!("Sending {} {} with Idempotency-Key {}", req.method, req.route().map(|r| r.as_str()).unwrap_or(""), key);
+        }
+        if Client::absolute(req) {
+            let url = try!(Client::url(endpoint, req));
+            let (res, chunks) = try!(Client::send(endpoint, req, &url, headers, stream));
+            return Ok((res, chunks, url));
+        }
+
+        let mut tried = Vec::new();
+
+        loop {
+            let host = endpoint.hosts.pick(&tried).unwrap_or(0);
+            let url = try!(Client::url_on(endpoint.hosts.url(host), req));
+
+            let err = match Client::send(endpoint, req, &url, headers, stream) {
+                Ok((res, chunks)) => {
+                    endpoint.hosts.mark_up(host);
+                    return Ok((res, chunks, url));
+                },
+                Err(err) => err
+            };
+
+            let unreachable = match err {
+                Error::HttpError(_) | Error::IoError(_) => true,
+                _ => false
+            };
+            if !unreachable || endpoint.hosts.len() < 2 {
+                return Err(err);
+            }
+
+            endpoint.hosts.mark_down(host);
+            tried.push(host);
+            if !Client::idempotent(req) || !Client::replayable(req) || tried.len() >= endpoint.hosts.len() {
+                return Err(err);
+            }
+            match req.idempotency_key() {
+                Some(key) => info!("Failing over from {} with Idempotency-Key {}: {}", url, key, err),
+                None => info!("Failing over from {}: {}", url, err)
+            }
+        }
+    }
+
+    /// POST and PATCH are only safe to resend when they carry an `Idempotency-Key`.
+    fn idempotent(request: &Request) -> bool {
+        match request.method {
+            hyper::Method::Post | hyper::Method::Patch => request.idempotency_key().is_some(),
+            _ => true
+        }
+    }
+
+    fn replayable(request: &Request) -> bool {
+        request.body.as_ref().map_or(true, |body| body.replayable())
+    }
+
+    fn store_cookies(endpoint: &Endpoint, url: &Url, headers: &Headers)
+    {
+        if let (Some(ref jar), Some(values)) = (endpoint.cookies.as_ref(), headers.get_raw("Set-Cookie")) {
+            let set_cookies: Vec<String> = values.iter().map(|value| String::from_utf8_lossy(value).into_owned()).collect();
+            jar.store(url, &set_cookies);
+        }
+    }
+
+    /// Applies what a response or failure tells about the service to the endpoint.
+    fn settle(endpoint: &Endpoint, outcome: &Outcome)
+    {
+        match *outcome {
+            Outcome::Response(ref url, ref status, ref headers) => {
+                endpoint.limiter.update(status, headers);
+                Client::store_cookies(endpoint, url, headers);
+                if let Some(ref breaker) = endpoint.breaker {
+                    breaker.record_status(status.to_u16());
+                }
+            },
+            Outcome::Failed(failure) => if let Some(ref breaker) = endpoint.breaker {
+                if failure { breaker.record(false) } else { breaker.release() }
+            }
+        }
+    }
+
+    /// Adds the jar's cookies for `url` to any `Cookie` header the request already has.
+    fn add_cookies(jar: &CookieJar, url: &Url, headers: &mut HashMap<String, String>)
+    {
+        let cookies = match jar.header(url) {
+            Some(cookies) => cookies,
+            None => return
+        };
+        let existing = headers.keys().find(|key| key.to_lowercase() == "cookie").cloned();
+        match existing.and_then(|key| headers.remove(&key)) {
+            Some(own) => headers.insert("Cookie".to_string(), format!("{}; {}", own, cookies)),
+            None => headers.insert("Cookie".to_string(), cookies)
+        };
+    }
+
+    fn send(endpoint: &Endpoint, request: &Request, url: &Url, headers: &HashMap<String, String>, stream: bool) -> Result<(Response, Option<ChunkReceiver>), Error>
+    {
+        let client = endpoint.client.clone();
+        let (tx, rx) = mpsc::channel();
+        let (chunk_tx, chunk_rx) = if stream {
+            let (chunk_tx, chunk_rx) = mpsc::sync_channel(STREAM_BUFFER);
+            (Some(chunk_tx), Some(chunk_rx))
+        } else {
+            (None, None)
+        };
+
+        // fail fast before a one-off upload is opened and used up
+        if let Some(ref breaker) = endpoint.breaker {
+            try!(breaker.allow());
+        }
+        let upload = match request.body {
+            Some(ref body) => match body.open() {
+                Ok(source) => Some(Upload::new(source)),
+                Err(e) => {
+                    if let Some(ref breaker) = endpoint.breaker {
+                        breaker.release();
+                    }
+                    return Err(e);
+                }
+            },
+            None => None
+        };
 
-        println!("the rpute is: {}", route);
-        println!("the url is: {}", url.to_string());
+        let mut headers = headers.clone();
+        if let Some(ref jar) = endpoint.cookies {
+            Client::add_cookies(jar, url, &mut headers);
+        }
+        let forwarding = match endpoint.proxies.as_ref().and_then(|proxies| proxies.for_url(url)) {
+            Some(proxy) if proxy.kind == ProxyKind::Http && url.scheme() == "http" && endpoint.unix_socket.is_none() => {
+                if let Some(authorization) = proxy.authorization() {
+                    headers.insert("Proxy-Authorization".to_string(), authorization);
+                }
+                true
+            },
+            _ => false
+        };
 
-        let _ = client.request(url, handler);
+        let handler = Handler {
+            request: request.clone(),
+            url: url.clone(),
+            headers: headers,
+            response: None,
+            decompress: endpoint.decompress,
+            max_body_size: endpoint.max_body_size,
+            body: None,
+            upload: upload,
+            stream: chunk_tx,
+            head_sent: false,
+            absolute_form: forwarding,
+            sender: tx,
+            user_agent: "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_11_6) \
+            AppleWebKit/537.36 (KHTML, like Gecko) \
+            Chrome/52.0.2743.116 Safari/537.36".to_owned(),
+        };
 
-        let (_, res) = rx.recv().unwrap();
-        client.close();
+        endpoint.limiter.acquire();
+        // lookups, connects and proxy handshakes happen on this thread, the event loop only picks up the socket
+        if let Some(ref dialer) = endpoint.dialer {
+            dialer.dial(url);
+        }
+        if client.request(url.clone(), handler).is_err() {
+            if let Some(ref dialer) = endpoint.dialer {
+                dialer.discard(url);
+            }
+            if let Some(ref breaker) = endpoint.breaker {
+                breaker.release();
+            }
+            return Err(Error::from(io::Error::new(io::ErrorKind::Other, "the endpoint's client has shut down")));
+        }
 
-        println!("at response");
-        let v = res.unwrap().body.unwrap();
-        let body_string = String::from_utf8(v).unwrap();
+        // the handler is dropped without answering if its event loop goes away
+        let res = match rx.recv() {
+            Ok((_, res)) => res,
+            Err(_) => Err(Error::from(hyper::Error::Incomplete))
+        };
+        let outcome = match res {
+            Ok(ref res) => Outcome::Response(url.clone(), res.status.clone(), res.headers.clone()),
+            Err(ref e) => Outcome::Failed(breaker::is_failure(e))
+        };
+        match endpoint.outcomes {
+            Some(ref outcomes) => outcomes.lock().unwrap().push(outcome),
+            None => Client::settle(endpoint, &outcome)
+        }
 
-        body_string
+        res.map(|res| (res, chunk_rx))
     }
 }
 
-impl hyper::client::Handler<HttpStream> for Handler {
+/// Generic over the transport, so TCP, TLS and Unix socket connectors share one handler.
+impl<T: Transport> hyper::client::Handler<T> for Handler {
     fn on_request(&mut self, req: &mut HyperRequest) -> Next {
         req.set_method(self.request.method.to_owned());
+        if self.absolute_form {
+            req.set_uri(RequestUri::AbsoluteUri(self.url.clone()));
+        }
         req.headers_mut().set(Connection::close());
         req.headers_mut().set(UserAgent(self.user_agent.clone()));
-        //req.headers_mut().set_raw("Content-Type","application/x-www-form-urlencoded");
-        if self.request.body.is_some() {
+        for (name, value) in self.headers.iter() {
+            req.headers_mut().set_raw(name.clone(), vec![value.as_bytes().to_vec()]);
+        }
+        if self.decompress && !req.headers().has::<AcceptEncoding>() {
+            req.headers_mut().set_raw("Accept-Encoding", vec![compression::ACCEPT_ENCODING.as_bytes().to_vec()]);
+        }
+        if let Some(ref body) = self.request.body {
+            match body.len() {
+                Some(len) => req.headers_mut().set(ContentLength(len)),
+                None => req.headers_mut().set(TransferEncoding(vec![TransferCoding::Chunked]))
+            }
             Next::write()
         } else {
             self.read()
         }
     }
 
-    fn on_request_writable(&mut self, _encoder: &mut Encoder<HttpStream>) -> Next {
-        println!("in on reuqest write");
-        if let Some(ref mut body) = self.request.body {
-            _encoder.write(body.as_bytes()).unwrap();
+    fn on_request_writable(&mut self, encoder: &mut Encoder<T>) -> Next {
+        loop {
+            let result = match self.upload {
+                Some(ref mut upload) => match upload.fill() {
+                    Ok(0) => None,
+                    Ok(_) => Some(encoder.write(upload.pending()).map(|n| { upload.pos += n; n })),
+                    Err(e) => Some(Err(e))
+                },
+                None => None
+            };
+            match result {
+                None => {
+                    self.upload = None;
+                    encoder.close();
+                    return self.read();
+                },
+                Some(Ok(0)) => return Next::write(),
+                Some(Ok(_)) => {},
+                Some(Err(ref e)) if e.kind() == io::ErrorKind::WouldBlock => return Next::write(),
+                Some(Err(e)) => {
+                    info!("Request write error for {}: {}", self.url, e);
+                    self.fail(Error::from(e));
+                    return Next::remove();
+                }
+            }
         }
-
-        _encoder.close();
-        Next::read()
     }
 
     fn on_response(&mut self, response: HyperResponse) -> Next {
         let status = response.status();
         let headers = response.headers();
-        //debug!("Got {} for {}", status, &self.request.route.unwrap());
+        let encoding = if self.decompress {
+            Handler::content_encoding(headers)
+        } else {
+            Encoding::Identity
+        };
+        let mut headers = headers.clone();
+        if encoding != Encoding::Identity {
+            headers.remove_raw("Content-Encoding");
+            headers.remove_raw("Content-Length");
+        }
         self.response = Some(Response {
             status: status.clone(),
-            headers: headers.clone(),
-            body: None
+            headers: headers,
+            body: None,
+            url: self.url.clone(),
+            redirects: Vec::new()
         });
-        println!("{}", status.to_string());
-        match status {
-            &StatusCode::Ok => {
-                println!("reading");
-                //if is_html(headers) {
-                self.read()
-                //} else {
-                //    self.return_response()
-                //}
-            },
-            &StatusCode::Created => {
-                println!("reading");
-                self.read()
-            }
-            _ => {
-                println!("not reading");
-                self.return_response()
-            }
+        debug!("Got {} for {}", status, self.url);
+        match *status {
+            StatusCode::NoContent | StatusCode::NotModified => self.return_response(),
+            _ if self.request.method == hyper::Method::Head => self.return_response(),
+            _ if redirect::is_redirect(status) => self.return_response(),
+            _ => self.read_body(encoding)
         }
     }
 
-    fn on_response_readable(&mut self, decoder: &mut Decoder<HttpStream>) -> Next {
-        let mut read_result = None;
-        if let Some(ref mut response) = self.response {
-            if response.body.is_none() {
-                response.body = Some(Vec::new());
+    fn on_response_readable(&mut self, decoder: &mut Decoder<T>) -> Next {
+        let read_result = match self.body {
+            Some(ref mut body) => io::copy(decoder, body),
+            None => {
+                self.fail(Error::from(hyper::Error::Incomplete));
+                return Next::end();
             }
-            if let Some(ref mut body) = response.body {
-                read_result = Some(io::copy(decoder, body));
-            }
-        }
-        if let Some(read_result) = read_result {
-            match read_result {
-                Ok(0) => self.return_response(),
-                Ok(_) => self.read(),
-                Err(e) => match e.kind() {
-                    io::ErrorKind::WouldBlock => Next::read(),
-                    _ => {
-                        //info!("Response read error for {}: {}", self.request.url, e);
-                        self.return_response()
+        };
+        match read_result {
+            Ok(0) => self.finish_body(),
+            Ok(_) => self.read(),
+            Err(e) => match e.kind() {
+                io::ErrorKind::WouldBlock => Next::read(),
+                _ => {
+                    if self.body.as_ref().map_or(false, |body| body.exceeded()) {
+                        let limit = self.max_body_size.unwrap_or(0);
+                        self.fail(Error::BodyTooLarge(limit));
+                        return Next::end();
+                    }
+                    if e.kind() == io::ErrorKind::BrokenPipe && self.head_sent {
+                        debug!("Response stream for {} was dropped", self.url);
+                        return Next::end();
                     }
+                    info!("Response read error for {}: {}", self.url, e);
+                    self.body = None;
+                    self.fail(Error::from(e));
+                    Next::end()
                 }
             }
-        } else {
-            panic!();
         }
-
     }
 
     fn on_error(&mut self, err: hyper::Error) -> Next {
-        println!("we have an error: {}", err);
-        //info!("Http error for {}: {}", self.request.url, err);
-        self.send_result();
+        info!("Http error for {}: {}", self.url, err);
+        self.fail(Error::from(err));
         Next::remove()
     }
 }
@@ -4,18 +4,81 @@ extern crate log;
 #[macro_use]
 extern crate quick_error;
 
+extern crate base64;
+extern crate brotli;
+extern crate erased_serde;
+extern crate flate2;
 extern crate hyper;
+extern crate mio;
+extern crate openssl;
+extern crate serde;
 extern crate serde_json;
+extern crate rand;
 extern crate regex;
 extern crate url;
 extern crate time;
 
+#[cfg(feature = "cbor")]
+extern crate serde_cbor;
+#[cfg(feature = "msgpack")]
+extern crate rmp_serde;
+#[cfg(feature = "xml")]
+extern crate serde_xml_rs;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(value) => value, None => return None })
+}
+
+mod breaker;
+mod bulkhead;
+mod cache;
+mod codec;
+mod compression;
+mod cookie;
+mod dial;
+mod hedge;
+mod hosts;
 mod http;
+mod multipart;
+mod paginate;
+mod patch;
+mod problem;
+mod proxy;
+mod ratelimit;
+mod redirect;
+mod resolve;
 mod serializer;
+mod tls;
+mod unix;
 mod validator;
 mod rest;
 mod error;
 
 //export
+pub use breaker::{CircuitBreaker, CircuitBreakerBuilder, CircuitState};
+pub use cache::{CacheEntry, CacheStore, DiskCache, MemoryCache};
+pub use codec::{Codec, JsonCodec, Visit};
+#[cfg(feature = "xml")]
+pub use codec::XmlCodec;
+#[cfg(feature = "msgpack")]
+pub use codec::MsgPackCodec;
+#[cfg(feature = "cbor")]
+pub use codec::CborCodec;
+#[cfg(feature = "yaml")]
+pub use codec::YamlCodec;
+pub use compression::Encoding;
+pub use cookie::{Cookie, CookieJar};
 pub use error::Error;
-pub use rest::{Client, Endpoint, Request, Method, Header};
+pub use hedge::HedgePolicy;
+pub use hosts::Balancing;
+pub use paginate::Pagination;
+pub use patch::{JsonPatch, MergePatch};
+pub use problem::Problem;
+pub use ratelimit::Quota;
+pub use redirect::RedirectPolicy;
+pub use resolve::{Resolver, SystemResolver};
+pub use serializer::BodyEncoding;
+pub use tls::{Tls, TlsBuilder, TlsVersion};
+pub use rest::{Client, Endpoint, Request, Response, Body, Chunks, Pages, Items, Method, Header};
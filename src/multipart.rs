@@ -0,0 +1,196 @@
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use time;
+
+use error::Error;
+use http::RequestBody;
+
+static BOUNDARY_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// A `multipart/form-data` body under construction.
+#[derive(Debug, Clone)]
+pub struct Form {
+    boundary: String,
+    parts: Vec<Part>
+}
+
+#[derive(Debug, Clone)]
+struct Part {
+    name: String,
+    filename: Option<String>,
+    mime: Option<String>,
+    body: RequestBody
+}
+
+impl Form {
+    pub fn new() -> Form {
+        let count = BOUNDARY_COUNTER.fetch_add(1, Ordering::SeqCst);
+        Form {
+            boundary: format!("------------------------{:016x}{:08x}", time::precise_time_ns(), count),
+            parts: Vec::new()
+        }
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    pub fn add_text(&mut self, name: &str, value: &str) {
+        self.parts.push(Part {
+            name: name.to_string(),
+            filename: None,
+            mime: None,
+            body: RequestBody::Bytes(value.as_bytes().to_vec())
+        });
+    }
+
+    pub fn add_file(&mut self, name: &str, body: RequestBody, filename: &str, mime: &str) {
+        self.parts.push(Part {
+            name: name.to_string(),
+            filename: Some(filename.to_string()),
+            mime: Some(mime.to_string()),
+            body: body
+        });
+    }
+
+    /// Chains every part into one streaming body, reopening the parts for every
+    /// send. The length is only known when every part has a known length.
+    pub fn body(&self) -> RequestBody {
+        let mut len = Some(0);
+        let mut parts = Vec::new();
+        for part in self.parts.iter() {
+            let head = part.head(&self.boundary).into_bytes();
+            len = match (len, part.body.len()) {
+                (Some(total), Some(part_len)) => Some(total + head.len() as u64 + part_len + 2),
+                _ => None
+            };
+            parts.push((head, part.body.clone()));
+        }
+        let tail = format!("--{}--\r\n", self.boundary).into_bytes();
+        len = len.map(|total| total + tail.len() as u64);
+
+        let replayable = self.parts.iter().all(|part| part.body.replayable());
+        let open = move || -> io::Result<Box<Read + Send>> {
+            let mut reader: Box<Read + Send> = Box::new(io::empty());
+            for &(ref head, ref body) in parts.iter() {
+                let part = try!(body.open().map_err(|e| match e {
+                    Error::IoError(e) => e,
+                    e => io::Error::new(io::ErrorKind::Other, e.to_string())
+                }));
+                reader = Box::new(reader
+                    .chain(Cursor::new(head.clone()))
+                    .chain(part)
+                    .chain(Cursor::new(b"\r\n".to_vec())));
+            }
+            Ok(Box::new(reader.chain(Cursor::new(tail.clone()))))
+        };
+
+        if replayable {
+            RequestBody::from_source(open, len)
+        } else {
+            // a one-off reader among the parts makes the whole form one-off
+            RequestBody::from_reader(Lazy { open: Box::new(open), reader: None }, len)
+        }
+    }
+}
+
+impl Part {
+    fn head(&self, boundary: &str) -> String {
+        let mut head = format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", boundary, escape(&self.name));
+        if let Some(ref filename) = self.filename {
+            head.push_str(&format!("; filename=\"{}\"", escape(filename)));
+        }
+        head.push_str("\r\n");
+        if let Some(ref mime) = self.mime {
+            head.push_str(&format!("Content-Type: {}\r\n", mime));
+        }
+        head.push_str("\r\n");
+        head
+    }
+}
+
+/// Opens its reader on first read.
+struct Lazy {
+    open: Box<Fn() -> io::Result<Box<Read + Send>> + Send>,
+    reader: Option<Box<Read + Send>>
+}
+
+impl Read for Lazy {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.reader.is_none() {
+            self.reader = Some(try!((self.open)()));
+        }
+        match self.reader {
+            Some(ref mut reader) => reader.read(buf),
+            None => unreachable!()
+        }
+    }
+}
+
+/// A body reading the file at `path`, opened anew for every send. Its length
+/// comes from the file's metadata when it can be read now.
+pub fn file(path: PathBuf) -> RequestBody {
+    let len = fs::metadata(&path).ok().map(|metadata| metadata.len());
+    RequestBody::from_source(move || {
+        let file = try!(File::open(&path));
+        Ok(Box::new(file) as Box<Read + Send>)
+    }, len)
+}
+
+/// Percent-encodes the characters that would break a quoted header parameter.
+fn escape(value: &str) -> String {
+    value.replace("\r", "%0D").replace("\n", "%0A").replace("\"", "%22")
+}
+
+#[test]
+fn form_body() {
+    let mut form = Form::new();
+    form.add_text("title", "foo");
+    form.add_file("file", RequestBody::Bytes(b"bar".to_vec()), "a\"b.txt", "text/plain");
+
+    let body = form.body();
+    let mut encoded = String::new();
+    body.open().unwrap().read_to_string(&mut encoded).unwrap();
+
+    let expected = format!("--{0}\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        foo\r\n\
+        --{0}\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a%22b.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        bar\r\n\
+        --{0}--\r\n", form.boundary());
+
+    assert_eq!(encoded, expected);
+    assert_eq!(body.len(), Some(expected.len() as u64));
+}
+
+#[test]
+fn form_file_replay() {
+    use std::env;
+    use std::io::Write;
+
+    let path = env::temp_dir().join(format!("rest-form-{}.txt", time::precise_time_ns()));
+    File::create(&path).unwrap().write_all(b"hello").unwrap();
+
+    let mut form = Form::new();
+    form.add_file("file", file(path.clone()), "hello.txt", "text/plain");
+    let body = form.body();
+
+    let mut first = String::new();
+    body.open().unwrap().read_to_string(&mut first).unwrap();
+    let mut second = String::new();
+    body.open().unwrap().read_to_string(&mut second).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(first.contains("\r\n\r\nhello\r\n"));
+    assert_eq!(first, second);
+    assert_eq!(body.len(), Some(first.len() as u64));
+}
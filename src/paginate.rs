@@ -0,0 +1,186 @@
+use serde_json::Value;
+use url::form_urlencoded;
+
+/// How to find the next page of a collection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pagination {
+    /// Follow the RFC 5988 `Link: <...>; rel="next"` response header.
+    Link,
+    /// Send the value found at the dotted `field` of the body as the `param` query parameter.
+    Cursor { param: String, field: String },
+    /// Count `param` up from `start`, asking for `size` items through `size_param`.
+    Page { param: String, size_param: String, start: u64, size: u64 },
+    /// Move `param` forward by `size` items, asking for `size` items through `limit_param`.
+    Offset { param: String, limit_param: String, size: u64 }
+}
+
+impl Pagination {
+    pub fn link() -> Pagination {
+        Pagination::Link
+    }
+
+    pub fn cursor(param: &str, field: &str) -> Pagination {
+        Pagination::Cursor {
+            param: param.to_string(),
+            field: field.to_string()
+        }
+    }
+
+    /// `page`/`per_page`, starting at page 1.
+    pub fn page(size: u64) -> Pagination {
+        Pagination::Page {
+            param: "page".to_string(),
+            size_param: "per_page".to_string(),
+            start: 1,
+            size: size
+        }
+    }
+
+    /// `offset`/`limit`, starting at offset 0.
+    pub fn offset(size: u64) -> Pagination {
+        Pagination::Offset {
+            param: "offset".to_string(),
+            limit_param: "limit".to_string(),
+            size: size
+        }
+    }
+
+    /// The route of the first page, with any paging parameters the strategy needs.
+    pub fn first_route(&self, route: &str) -> String {
+        match *self {
+            Pagination::Page { ref param, ref size_param, start, size } => {
+                let route = if query_value(route, param).is_none() {
+                    with_query(route, param, &start.to_string())
+                } else {
+                    route.to_string()
+                };
+                with_query(&route, size_param, &size.to_string())
+            },
+            Pagination::Offset { ref param, ref limit_param, size } => {
+                let route = if query_value(route, param).is_none() {
+                    with_query(route, param, "0")
+                } else {
+                    route.to_string()
+                };
+                with_query(&route, limit_param, &size.to_string())
+            },
+            _ => route.to_string()
+        }
+    }
+
+    /// The route of the page after `route`, or `None` when there is none.
+    pub fn next_route(&self, route: &str, link: Option<&str>, body: Option<&Value>) -> Option<String> {
+        match *self {
+            Pagination::Link => link.and_then(next_link),
+            Pagination::Cursor { ref param, ref field } => {
+                let cursor = match body.and_then(|body| lookup(body, field)) {
+                    Some(&Value::String(ref cursor)) if !cursor.is_empty() => cursor.clone(),
+                    Some(&Value::Number(ref cursor)) => cursor.to_string(),
+                    _ => return None
+                };
+                Some(with_query(route, param, &cursor))
+            },
+            Pagination::Page { ref param, start, .. } => {
+                let page = query_value(route, param).and_then(|page| page.parse::<u64>().ok()).unwrap_or(start);
+                Some(with_query(route, param, &(page + 1).to_string()))
+            },
+            Pagination::Offset { ref param, size, .. } => {
+                let offset = query_value(route, param).and_then(|offset| offset.parse::<u64>().ok()).unwrap_or(0);
+                Some(with_query(route, param, &(offset + size).to_string()))
+            }
+        }
+    }
+
+    /// Whether an unparseable page should end paging, as counting strategies would never stop.
+    pub fn needs_items(&self) -> bool {
+        match *self {
+            Pagination::Page { .. } | Pagination::Offset { .. } => true,
+            _ => false
+        }
+    }
+}
+
+/// Finds the `rel="next"` target in a `Link` header value.
+pub fn next_link(header: &str) -> Option<String> {
+    for link in header.split(',') {
+        let mut parts = link.split(';');
+        let target = parts.next().unwrap_or("").trim();
+        if !target.starts_with('<') || !target.ends_with('>') {
+            continue;
+        }
+        let next = parts.any(|param| {
+            let mut pair = param.splitn(2, '=');
+            let name = pair.next().unwrap_or("").trim();
+            let value = pair.next().unwrap_or("").trim().trim_matches('"');
+            name.to_lowercase() == "rel" && value.split_whitespace().any(|rel| rel.to_lowercase() == "next")
+        });
+        if next {
+            return Some(target[1..target.len() - 1].to_string());
+        }
+    }
+    None
+}
+
+/// Looks up a dotted path such as `meta.next_cursor`.
+pub fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = value;
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        current = try_opt!(current.get(key));
+    }
+    Some(current)
+}
+
+pub fn query_value(route: &str, name: &str) -> Option<String> {
+    let query = try_opt!(route.splitn(2, '?').nth(1));
+    form_urlencoded::parse(query.as_bytes())
+        .find(|&(ref key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Sets (or replaces) one query parameter of a route.
+pub fn with_query(route: &str, name: &str, value: &str) -> String {
+    let mut parts = route.splitn(2, '?');
+    let path = parts.next().unwrap_or("");
+    let query = parts.next().unwrap_or("");
+
+    let pairs: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .filter(|&(ref key, _)| key != name)
+        .collect();
+    let query = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs)
+        .append_pair(name, value)
+        .finish();
+    format!("{}?{}", path, query)
+}
+
+#[test]
+fn pagination_next_link() {
+    let header = r#"<https://api.example.com/items?page=1>; rel="prev", <https://api.example.com/items?page=3>; rel="next""#;
+    assert_eq!(next_link(header), Some("https://api.example.com/items?page=3".to_string()));
+    assert_eq!(next_link(r#"<https://api.example.com/items?page=1>; rel="first""#), None);
+}
+
+#[test]
+fn pagination_page_routes() {
+    let pagination = Pagination::page(20);
+
+    let first = pagination.first_route("posts?userId=1");
+    assert_eq!(first, "posts?userId=1&page=1&per_page=20");
+    assert_eq!(pagination.next_route(&first, None, None), Some("posts?userId=1&per_page=20&page=2".to_string()));
+
+    let offset = Pagination::offset(50);
+    assert_eq!(offset.next_route("posts?offset=50&limit=50", None, None), Some("posts?limit=50&offset=100".to_string()));
+}
+
+#[test]
+fn pagination_cursor() {
+    use serde_json;
+
+    let pagination = Pagination::cursor("cursor", "meta.next");
+    let body: Value = serde_json::from_str(r#"{"data": [1], "meta": {"next": "abc"}}"#).unwrap();
+    let last: Value = serde_json::from_str(r#"{"data": [], "meta": {"next": null}}"#).unwrap();
+
+    assert_eq!(pagination.next_route("posts", None, Some(&body)), Some("posts?cursor=abc".to_string()));
+    assert_eq!(pagination.next_route("posts", None, Some(&last)), None);
+}
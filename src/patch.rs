@@ -0,0 +1,234 @@
+use serde_json::{Map, Value};
+
+pub const JSON_PATCH: &'static str = "application/json-patch+json";
+pub const MERGE_PATCH: &'static str = "application/merge-patch+json";
+
+/// An RFC 6902 JSON Patch document, a list of operations applied in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPatch {
+    operations: Vec<Value>
+}
+
+/// An RFC 7396 JSON Merge Patch document. `null` members remove fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergePatch {
+    document: Value
+}
+
+impl JsonPatch {
+    pub fn new() -> JsonPatch {
+        JsonPatch {
+            operations: Vec::new()
+        }
+    }
+
+    /// Computes the operations that turn `from` into `to`.
+    pub fn diff(from: &Value, to: &Value) -> JsonPatch {
+        let mut patch = JsonPatch::new();
+        diff_into(&mut patch, "", from, to);
+        patch
+    }
+
+    pub fn add<V: Into<Value>>(&mut self, path: &str, value: V) -> &mut JsonPatch {
+        self.push("add", path, None, Some(value.into()))
+    }
+
+    pub fn remove(&mut self, path: &str) -> &mut JsonPatch {
+        self.push("remove", path, None, None)
+    }
+
+    pub fn replace<V: Into<Value>>(&mut self, path: &str, value: V) -> &mut JsonPatch {
+        self.push("replace", path, None, Some(value.into()))
+    }
+
+    pub fn move_value(&mut self, from: &str, path: &str) -> &mut JsonPatch {
+        self.push("move", path, Some(from), None)
+    }
+
+    pub fn copy_value(&mut self, from: &str, path: &str) -> &mut JsonPatch {
+        self.push("copy", path, Some(from), None)
+    }
+
+    pub fn test<V: Into<Value>>(&mut self, path: &str, value: V) -> &mut JsonPatch {
+        self.push("test", path, None, Some(value.into()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Array(self.operations.clone())
+    }
+
+    fn push(&mut self, op: &str, path: &str, from: Option<&str>, value: Option<Value>) -> &mut JsonPatch {
+        let mut operation = Map::new();
+        operation.insert("op".to_string(), Value::from(op));
+        if let Some(from) = from {
+            operation.insert("from".to_string(), Value::from(from));
+        }
+        operation.insert("path".to_string(), Value::from(path));
+        if let Some(value) = value {
+            operation.insert("value".to_string(), value);
+        }
+        self.operations.push(Value::Object(operation));
+        self
+    }
+}
+
+impl MergePatch {
+    pub fn new() -> MergePatch {
+        MergePatch {
+            document: Value::Object(Map::new())
+        }
+    }
+
+    /// Computes the merge patch that turns `from` into `to`. When `to` is not
+    /// an object the patch is `to` itself, replacing the whole target.
+    pub fn diff(from: &Value, to: &Value) -> MergePatch {
+        MergePatch {
+            document: merge_diff(from, to)
+        }
+    }
+
+    /// Sets the field at a dotted `path`; see `set_at` for names containing dots.
+    pub fn set<V: Into<Value>>(&mut self, path: &str, value: V) -> &mut MergePatch {
+        let keys: Vec<&str> = path.split('.').collect();
+        self.set_at(&keys, value)
+    }
+
+    /// Sets the field reached through `keys`, one member name per level.
+    pub fn set_at<V: Into<Value>>(&mut self, keys: &[&str], value: V) -> &mut MergePatch {
+        insert_at(&mut self.document, keys, value.into());
+        self
+    }
+
+    /// Removes the field at a dotted `path`, sent as an explicit `null`.
+    pub fn remove(&mut self, path: &str) -> &mut MergePatch {
+        self.set(path, Value::Null)
+    }
+
+    /// Removes the field reached through `keys`, sent as an explicit `null`.
+    pub fn remove_at(&mut self, keys: &[&str]) -> &mut MergePatch {
+        self.set_at(keys, Value::Null)
+    }
+
+    pub fn to_value(&self) -> Value {
+        self.document.clone()
+    }
+}
+
+/// Sets `value` under `keys`, turning anything in the way into objects. The
+/// last value set for a field wins, as later patch operations override earlier ones.
+fn insert_at(document: &mut Value, keys: &[&str], value: Value) {
+    let (first, rest) = match keys.split_first() {
+        Some(split) => split,
+        None => {
+            *document = value;
+            return;
+        }
+    };
+    if !document.is_object() {
+        *document = Value::Object(Map::new());
+    }
+    if let Value::Object(ref mut map) = *document {
+        let entry = map.entry(first.to_string()).or_insert(Value::Null);
+        insert_at(entry, rest, value);
+    }
+}
+
+/// Escapes a member name for use in a JSON Pointer (RFC 6901).
+fn pointer(parent: &str, key: &str) -> String {
+    format!("{}/{}", parent, key.replace("~", "~0").replace("/", "~1"))
+}
+
+fn diff_into(patch: &mut JsonPatch, path: &str, from: &Value, to: &Value) {
+    if from == to {
+        return;
+    }
+    match (from, to) {
+        (&Value::Object(ref from), &Value::Object(ref to)) => {
+            for (key, value) in from.iter() {
+                match to.get(key) {
+                    Some(other) => diff_into(patch, &pointer(path, key), value, other),
+                    None => { patch.remove(&pointer(path, key)); }
+                }
+            }
+            for (key, value) in to.iter() {
+                if !from.contains_key(key) {
+                    patch.add(&pointer(path, key), value.clone());
+                }
+            }
+        },
+        _ => { patch.replace(path, to.clone()); }
+    }
+}
+
+fn merge_diff(from: &Value, to: &Value) -> Value {
+    match (from, to) {
+        (&Value::Object(ref from), &Value::Object(ref to)) => {
+            let mut document = Map::new();
+            for key in from.keys() {
+                if !to.contains_key(key) {
+                    document.insert(key.clone(), Value::Null);
+                }
+            }
+            for (key, value) in to.iter() {
+                match from.get(key) {
+                    Some(old) if old == value => {},
+                    Some(old) => { document.insert(key.clone(), merge_diff(old, value)); },
+                    None => { document.insert(key.clone(), value.clone()); }
+                }
+            }
+            Value::Object(document)
+        },
+        _ => to.clone()
+    }
+}
+
+#[test]
+fn json_patch_diff() {
+    use serde_json;
+
+    let from: Value = serde_json::from_str(r#"{"title": "foo", "tags": ["a"], "a/b": 1, "meta": {"views": 1}}"#).unwrap();
+    let to: Value = serde_json::from_str(r#"{"title": "bar", "tags": ["a"], "meta": {"views": 2, "likes": 0}}"#).unwrap();
+
+    let mut expected = JsonPatch::new();
+    expected.remove("/a~1b")
+        .replace("/meta/views", 2)
+        .add("/meta/likes", 0)
+        .replace("/title", "bar");
+
+    assert_eq!(JsonPatch::diff(&from, &to), expected);
+}
+
+#[test]
+fn merge_patch_diff() {
+    use serde_json;
+
+    let from: Value = serde_json::from_str(r#"{"title": "foo", "body": "bar", "meta": {"views": 1, "draft": true}}"#).unwrap();
+    let to: Value = serde_json::from_str(r#"{"title": "foo", "meta": {"views": 2, "draft": true}}"#).unwrap();
+
+    let mut expected = MergePatch::new();
+    expected.remove("body").set("meta.views", 2);
+
+    assert_eq!(MergePatch::diff(&from, &to), expected);
+}
+
+#[test]
+fn merge_patch_replace_and_dotted_keys() {
+    let from: Value = Value::from(vec![1, 2]);
+    assert_eq!(MergePatch::diff(&from, &Value::from("x")).to_value(), Value::from("x"));
+
+    let mut patch = MergePatch::new();
+    patch.set_at(&["labels", "app.kubernetes.io/name"], "web").set("meta.views", 1).set("meta.views", 2);
+
+    let mut labels = Map::new();
+    labels.insert("app.kubernetes.io/name".to_string(), Value::from("web"));
+    let mut meta = Map::new();
+    meta.insert("views".to_string(), Value::from(2));
+    let mut expected = Map::new();
+    expected.insert("labels".to_string(), Value::Object(labels));
+    expected.insert("meta".to_string(), Value::Object(meta));
+    assert_eq!(patch.to_value(), Value::Object(expected));
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use serde_json::{self, Map, Value};
+
+use codec;
+
+pub const PROBLEM_JSON: &'static str = "application/problem+json";
+
+/// An RFC 7807 problem details object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// The `type` URI, `about:blank` when the server left it out.
+    pub type_uri: String,
+    pub title: Option<String>,
+    pub status: Option<u16>,
+    pub detail: Option<String>,
+    pub instance: Option<String>,
+    /// Every member that is not one of the standard ones above.
+    pub extensions: Map<String, Value>
+}
+
+impl Problem {
+    /// Parses `body` if `content_type` says it is `application/problem+json`.
+    pub fn parse(content_type: Option<&str>, body: &[u8]) -> Option<Problem> {
+        match content_type {
+            Some(content_type) if codec::media_type(content_type) == PROBLEM_JSON => {},
+            _ => return None
+        }
+        match serde_json::from_slice(body) {
+            Ok(Value::Object(members)) => Some(Problem::from_members(members)),
+            _ => None
+        }
+    }
+
+    /// Whether this problem has the given `type` URI.
+    pub fn is(&self, type_uri: &str) -> bool {
+        self.type_uri == type_uri
+    }
+
+    fn from_members(mut members: Map<String, Value>) -> Problem {
+        let type_uri = string(members.remove("type")).unwrap_or("about:blank".to_string());
+        let title = string(members.remove("title"));
+        let status = members.remove("status").and_then(|status| status.as_u64()).map(|status| status as u16);
+        let detail = string(members.remove("detail"));
+        let instance = string(members.remove("instance"));
+        Problem {
+            type_uri: type_uri,
+            title: title,
+            status: status,
+            detail: detail,
+            instance: instance,
+            extensions: members
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(status) = self.status {
+            try!(write!(f, "{} ", status));
+        }
+        try!(write!(f, "{}", self.title.as_ref().unwrap_or(&self.type_uri)));
+        if let Some(ref detail) = self.detail {
+            try!(write!(f, ": {}", detail));
+        }
+        Ok(())
+    }
+}
+
+fn string(value: Option<Value>) -> Option<String> {
+    match value {
+        Some(Value::String(text)) => Some(text),
+        _ => None
+    }
+}
+
+#[test]
+fn problem_parse() {
+    let body = br#"{
+        "type": "https://example.com/probs/out-of-credit",
+        "title": "You do not have enough credit.",
+        "status": 403,
+        "detail": "Your current balance is 30, but that costs 50.",
+        "instance": "/account/12345/msgs/abc",
+        "balance": 30
+    }"#;
+
+    let problem = Problem::parse(Some("application/problem+json; charset=utf-8"), body).unwrap();
+    assert!(problem.is("https://example.com/probs/out-of-credit"));
+    assert_eq!(problem.status, Some(403));
+    assert_eq!(problem.instance, Some("/account/12345/msgs/abc".to_string()));
+    assert_eq!(problem.extensions.get("balance"), Some(&Value::from(30)));
+    assert_eq!(problem.to_string(), "403 You do not have enough credit.: Your current balance is 30, but that costs 50.");
+
+    assert_eq!(Problem::parse(Some("application/json"), body), None);
+}
@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hyper::header::Headers;
+use hyper::status::StatusCode;
+
+use cache;
+
+/// Once less than this share of the server quota is left, the remaining
+/// requests are spread evenly over what is left of the window.
+const SLOW_DOWN_BELOW: f64 = 0.1;
+
+/// `X-RateLimit-Reset` values above this are epoch seconds rather than a delay.
+const EPOCH_THRESHOLD: u64 = 1_000_000_000;
+
+/// The quota a server last reported, see `Endpoint::quota`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quota {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Time left until the quota resets.
+    pub reset: Option<Duration>,
+    /// Time left on a `Retry-After` pause.
+    pub retry_after: Option<Duration>
+}
+
+/// Paces the requests of one endpoint, both to a fixed `request_limit` and to
+/// whatever the server reports in its rate-limit headers.
+pub struct RateLimiter {
+    state: Mutex<State>
+}
+
+struct State {
+    window: Option<(usize, Duration)>,
+    sent: VecDeque<Instant>,
+    last: Option<Instant>,
+    limit: Option<u64>,
+    remaining: Option<u64>,
+    reset: Option<Instant>,
+    paused_until: Option<Instant>
+}
+
+impl RateLimiter {
+    /// `window` allows at most that many requests per duration on top of the server's quota.
+    pub fn new(window: Option<(usize, Duration)>) -> RateLimiter {
+        RateLimiter {
+            state: Mutex::new(State {
+                window: window,
+                sent: VecDeque::new(),
+                last: None,
+                limit: None,
+                remaining: None,
+                reset: None,
+                paused_until: None
+            })
+        }
+    }
+
+    /// Blocks until the next request may go out.
+    pub fn acquire(&self) {
+        let delay = self.reserve(Instant::now());
+        if delay > Duration::from_millis(0) {
+            debug!("Rate limited, waiting {:?}", delay);
+            thread::sleep(delay);
+        }
+    }
+
+    /// Books a slot for a request wanting to go out at `now` and returns how long it has to wait.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+
+        if state.reset.map_or(false, |reset| reset <= now) {
+            state.remaining = state.limit;
+            state.reset = None;
+        }
+
+        let mut start = now;
+        if let Some(pause) = state.paused_until {
+            start = later(start, pause);
+        }
+
+        if let Some((requests, window)) = state.window {
+            while state.sent.front().map_or(false, |sent| *sent + window <= now) {
+                state.sent.pop_front();
+            }
+            if requests > 0 && state.sent.len() >= requests {
+                let slot = state.sent[state.sent.len() - requests] + window;
+                start = later(start, slot);
+            }
+        }
+
+        match (state.remaining, state.reset) {
+            (Some(0), Some(reset)) => start = later(start, reset),
+            (Some(remaining), Some(reset)) => {
+                let low = state.limit.map_or(false, |limit| (remaining as f64) < limit as f64 * SLOW_DOWN_BELOW);
+                if let (true, Some(last)) = (low, state.last) {
+                    let left = reset - later(now, last).min(reset);
+                    start = later(start, last + left / (remaining as u32 + 1));
+                }
+            },
+            _ => {}
+        }
+
+        if state.window.is_some() {
+            state.sent.push_back(start);
+        }
+        state.remaining = state.remaining.map(|remaining| remaining.saturating_sub(1));
+        state.last = Some(start);
+        start - now
+    }
+
+    /// Takes in the rate-limit headers of a response.
+    pub fn update(&self, status: &StatusCode, headers: &Headers) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        let combined = header(headers, "RateLimit").map(|value| parameters(&value)).unwrap_or(Vec::new());
+        let find = |name: &str| combined.iter().find(|&&(ref key, _)| key == name).map(|&(_, value)| value);
+
+        let limit = number(headers, "RateLimit-Limit").or(find("limit")).or(number(headers, "X-RateLimit-Limit"));
+        let remaining = number(headers, "RateLimit-Remaining").or(find("remaining")).or(number(headers, "X-RateLimit-Remaining"));
+        let reset = number(headers, "RateLimit-Reset").or(find("reset"))
+            .or(number(headers, "X-RateLimit-Reset").map(|reset| {
+                if reset > EPOCH_THRESHOLD {
+                    reset.saturating_sub(cache::now() as u64)
+                } else {
+                    reset
+                }
+            }));
+
+        if limit.is_some() {
+            state.limit = limit;
+        }
+        if remaining.is_some() {
+            state.remaining = remaining;
+        }
+        if let Some(reset) = reset {
+            state.reset = Some(now + Duration::from_secs(reset));
+        }
+
+        if *status == StatusCode::TooManyRequests || *status == StatusCode::ServiceUnavailable {
+            let pause = header(headers, "Retry-After").and_then(|value| retry_after(&value));
+            let until = match (pause, state.reset) {
+                (Some(pause), _) => Some(now + pause),
+                (None, Some(reset)) if *status == StatusCode::TooManyRequests => Some(reset),
+                (None, _) => None
+            };
+            if let Some(until) = until {
+                warn!("Server asked us to back off for {:?}", until - now.min(until));
+                state.paused_until = Some(until);
+            }
+        }
+    }
+
+    pub fn quota(&self) -> Quota {
+        let now = Instant::now();
+        let state = self.state.lock().unwrap();
+        let left = |until: Option<Instant>| until.and_then(|until| if until > now { Some(until - now) } else { None });
+        Quota {
+            limit: state.limit,
+            remaining: state.remaining,
+            reset: left(state.reset),
+            retry_after: left(state.paused_until)
+        }
+    }
+}
+
+fn later(a: Instant, b: Instant) -> Instant {
+    if b > a { b } else { a }
+}
+
+fn header(headers: &Headers, name: &str) -> Option<String> {
+    headers.get_raw(name)
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+/// The leading number of a header, ignoring policies like `100, 100;w=60`.
+fn number(headers: &Headers, name: &str) -> Option<u64> {
+    header(headers, name).and_then(|value| {
+        value.split(|c: char| c == ',' || c == ';').next().and_then(|first| first.trim().parse().ok())
+    })
+}
+
+/// `limit=100, remaining=50, reset=5`
+fn parameters(value: &str) -> Vec<(String, u64)> {
+    value.split(|c: char| c == ',' || c == ';')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim().to_lowercase();
+            parts.next().and_then(|value| value.trim().parse().ok()).map(|value| (key, value))
+        })
+        .collect()
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(value: &str) -> Option<Duration> {
+    match value.trim().parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => cache::http_date(value).map(|date| Duration::from_secs(::std::cmp::max(date - cache::now(), 0) as u64))
+    }
+}
+
+#[test]
+fn rate_limiter_headers() {
+    let limiter = RateLimiter::new(None);
+
+    let mut headers = Headers::new();
+    headers.set_raw("X-RateLimit-Limit", vec![b"60".to_vec()]);
+    headers.set_raw("X-RateLimit-Remaining", vec![b"0".to_vec()]);
+    headers.set_raw("RateLimit", vec![b"limit=100, remaining=5, reset=30".to_vec()]);
+    headers.set_raw("Retry-After", vec![b"10".to_vec()]);
+    limiter.update(&StatusCode::TooManyRequests, &headers);
+
+    let quota = limiter.quota();
+    assert_eq!(quota.limit, Some(100));
+    assert_eq!(quota.remaining, Some(5));
+    assert!(quota.reset.unwrap() > Duration::from_secs(29));
+    assert!(quota.retry_after.unwrap() > Duration::from_secs(9));
+}
+
+#[test]
+fn rate_limiter_window() {
+    let limiter = RateLimiter::new(Some((2, Duration::from_secs(1))));
+    let now = Instant::now();
+
+    assert_eq!(limiter.reserve(now), Duration::from_millis(0));
+    assert_eq!(limiter.reserve(now), Duration::from_millis(0));
+    assert_eq!(limiter.reserve(now), Duration::from_secs(1));
+}
+
+#[test]
+fn rate_limiter_exhausted() {
+    let limiter = RateLimiter::new(None);
+
+    let mut headers = Headers::new();
+    headers.set_raw("RateLimit-Limit", vec![b"10".to_vec()]);
+    headers.set_raw("RateLimit-Remaining", vec![b"0".to_vec()]);
+    headers.set_raw("RateLimit-Reset", vec![b"5".to_vec()]);
+    limiter.update(&StatusCode::Ok, &headers);
+
+    assert!(limiter.reserve(Instant::now()) > Duration::from_secs(4));
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use hyper::status::StatusCode;
+use hyper;
+
+use url::Url;
+
+/// Controls which redirects an `Endpoint` follows.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedirectPolicy
+{
+    /// Never follow redirects, the 3xx response is returned as is.
+    None,
+    /// Follow up to the given number of hops.
+    Limit(usize),
+    /// Follow up to the given number of hops, as long as they stay on the same origin.
+    SameOrigin(usize)
+}
+
+impl RedirectPolicy {
+    pub fn max_hops(&self) -> usize {
+        match *self {
+            RedirectPolicy::None => 0,
+            RedirectPolicy::Limit(hops) => hops,
+            RedirectPolicy::SameOrigin(hops) => hops
+        }
+    }
+
+    /// A zero hop limit means redirects aren't followed, like `None`.
+    pub fn allows(&self, from: &Url, to: &Url) -> bool {
+        match *self {
+            RedirectPolicy::None | RedirectPolicy::Limit(0) | RedirectPolicy::SameOrigin(0) => false,
+            RedirectPolicy::Limit(_) => true,
+            RedirectPolicy::SameOrigin(_) => same_origin(from, to)
+        }
+    }
+}
+
+pub fn is_redirect(status: &StatusCode) -> bool {
+    match *status {
+        StatusCode::MovedPermanently |
+        StatusCode::Found |
+        StatusCode::SeeOther |
+        StatusCode::TemporaryRedirect |
+        StatusCode::PermanentRedirect => true,
+        _ => false
+    }
+}
+
+pub fn same_origin(from: &Url, to: &Url) -> bool {
+    from.origin() == to.origin()
+}
+
+/// Returns the method for the next hop and whether the body is kept.
+///
+/// 303 always switches to GET (except HEAD), 301/302 only rewrite POST the
+/// way every user agent does, and 307/308 must replay method and body unchanged.
+pub fn next_method(status: &StatusCode, method: &hyper::Method) -> (hyper::Method, bool) {
+    match *status {
+        StatusCode::SeeOther => match *method {
+            hyper::Method::Head => (hyper::Method::Head, false),
+            _ => (hyper::Method::Get, false)
+        },
+        StatusCode::MovedPermanently | StatusCode::Found => match *method {
+            hyper::Method::Post => (hyper::Method::Get, false),
+            _ => (method.clone(), true)
+        },
+        _ => (method.clone(), true)
+    }
+}
+
+/// Drops credentials before a request leaves the origin they were meant for.
+pub fn strip_auth_headers(headers: &mut HashMap<String, String>) {
+    let names: Vec<String> = headers.keys()
+        .filter(|name| name.to_lowercase() == "authorization")
+        .cloned()
+        .collect();
+    for name in names {
+        headers.remove(&name);
+    }
+}
+
+/// Headers that describe the request body, dropped with it when a redirect switches to GET.
+const BODY_HEADERS: [&'static str; 4] = ["content-type", "content-length", "content-encoding", "transfer-encoding"];
+
+pub fn strip_body_headers(headers: &mut HashMap<String, String>) {
+    let names: Vec<String> = headers.keys()
+        .filter(|name| BODY_HEADERS.contains(&name.to_lowercase().as_str()))
+        .cloned()
+        .collect();
+    for name in names {
+        headers.remove(&name);
+    }
+}
+
+#[test]
+fn redirect_policy_same_origin() {
+    let from = Url::parse("http://example.com/a").unwrap();
+    let same = Url::parse("http://example.com/b").unwrap();
+    let other = Url::parse("https://example.com/b").unwrap();
+
+    assert_eq!(RedirectPolicy::SameOrigin(5).allows(&from, &same), true);
+    assert_eq!(RedirectPolicy::SameOrigin(5).allows(&from, &other), false);
+    assert_eq!(RedirectPolicy::Limit(5).allows(&from, &other), true);
+    assert_eq!(RedirectPolicy::None.allows(&from, &same), false);
+    assert_eq!(RedirectPolicy::Limit(0).allows(&from, &same), false);
+    assert_eq!(RedirectPolicy::SameOrigin(0).allows(&from, &same), false);
+}
+
+#[test]
+fn redirect_next_method() {
+    assert_eq!(next_method(&StatusCode::SeeOther, &hyper::Method::Post), (hyper::Method::Get, false));
+    assert_eq!(next_method(&StatusCode::SeeOther, &hyper::Method::Head), (hyper::Method::Head, false));
+    assert_eq!(next_method(&StatusCode::Found, &hyper::Method::Post), (hyper::Method::Get, false));
+    assert_eq!(next_method(&StatusCode::Found, &hyper::Method::Put), (hyper::Method::Put, true));
+    assert_eq!(next_method(&StatusCode::TemporaryRedirect, &hyper::Method::Post), (hyper::Method::Post, true));
+    assert_eq!(next_method(&StatusCode::PermanentRedirect, &hyper::Method::Patch), (hyper::Method::Patch, true));
+}
+
+#[test]
+fn redirect_strip_body_headers() {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    headers.insert("content-encoding".to_string(), "gzip".to_string());
+    headers.insert("Accept".to_string(), "application/json".to_string());
+    strip_body_headers(&mut headers);
+
+    assert_eq!(headers.keys().collect::<Vec<_>>(), vec!["Accept"]);
+}
+
+#[test]
+fn redirect_strip_auth_headers() {
+    let mut headers = HashMap::new();
+    headers.insert("authorization".to_string(), "Bearer secret".to_string());
+    headers.insert("Accept".to_string(), "application/json".to_string());
+    strip_auth_headers(&mut headers);
+
+    assert_eq!(headers.keys().collect::<Vec<_>>(), vec!["Accept"]);
+}
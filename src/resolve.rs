@@ -0,0 +1,180 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{SocketAddr, TcpStream as StdTcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::client::{Connect, DefaultTransport, Registration};
+use hyper::net::{HttpStream, HttpsStream, Ssl};
+use mio::tcp::TcpStream;
+use url::Url;
+
+use dial::{self, Dialer};
+use tls::Tls;
+
+/// Turns a host name into the addresses to connect to, see `EndpointBuilder::resolver`.
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// The system resolver, as used when nothing else is configured.
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(try!((bare(host), port).to_socket_addrs()).collect())
+    }
+}
+
+/// Fixed addresses for some hosts, like curl's `--resolve`, and another resolver for the rest.
+/// As with curl an address only stands in for its host on the address's own port.
+pub struct Overrides {
+    hosts: HashMap<(String, u16), SocketAddr>,
+    fallback: Arc<Resolver>
+}
+
+impl Overrides {
+    pub fn new(hosts: Vec<(String, SocketAddr)>, fallback: Arc<Resolver>) -> Overrides {
+        Overrides {
+            hosts: hosts.into_iter().map(|(host, addr)| ((bare(&host).to_lowercase(), addr.port()), addr)).collect(),
+            fallback: fallback
+        }
+    }
+}
+
+impl Resolver for Overrides {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        match self.hosts.get(&(bare(host).to_lowercase(), port)) {
+            Some(addr) => Ok(vec![*addr]),
+            None => self.fallback.resolve(host, port)
+        }
+    }
+}
+
+/// `[::1]` as URLs write it, `::1` as resolvers expect it.
+pub fn bare(host: &str) -> &str {
+    host.trim_matches(|c| c == '[' || c == ']')
+}
+
+/// Hands hyper's event loop the connections a `Dialer` set up, whether directly
+/// to the addresses a `Resolver` picks or through a proxy, while keeping the URL's
+/// host for the `Host` header and TLS server name.
+///
+/// The request's own thread resolves and dials before the request reaches the
+/// loop, so the socket is waiting by the time `connect` is called and nothing
+/// has to be handed back later through the `Registration`.
+pub struct ResolvingConnector {
+    dialer: Arc<Dialer>,
+    tls: Tls,
+    connected: VecDeque<(dial::Key, io::Result<DefaultTransport>)>
+}
+
+impl ResolvingConnector {
+    pub fn new(dialer: Arc<Dialer>, tls: Tls) -> ResolvingConnector {
+        ResolvingConnector {
+            dialer: dialer,
+            tls: tls,
+            connected: VecDeque::new()
+        }
+    }
+
+    fn wrap(&self, url: &Url, key: &dial::Key) -> io::Result<DefaultTransport> {
+        let stream = HttpStream(try!(TcpStream::from_stream(try!(self.dialer.take(key)))));
+        if url.scheme() == "https" {
+            self.tls.wrap_client(stream, &key.1)
+                .map(HttpsStream::Https)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+        } else {
+            Ok(HttpsStream::Http(stream))
+        }
+    }
+}
+
+/// Connects to the first of `host`'s addresses that answers within `timeout`.
+pub fn connect(resolver: &Resolver, host: &str, port: u16, timeout: Duration) -> io::Result<StdTcpStream> {
+    let mut last = io::Error::new(io::ErrorKind::NotFound, format!("no addresses for {}", host));
+    for addr in try!(resolver.resolve(host, port)) {
+        debug!("Connecting to {} at {}", host, addr);
+        match StdTcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last = err
+        }
+    }
+    Err(last)
+}
+
+impl Connect for ResolvingConnector {
+    type Output = DefaultTransport;
+    type Key = dial::Key;
+
+    fn key(&self, url: &Url) -> Option<Self::Key> {
+        dial::key(url)
+    }
+
+    fn connect(&mut self, url: &Url) -> io::Result<Self::Key> {
+        let key = try!(dial::key(url).ok_or(io::Error::new(io::ErrorKind::InvalidInput, "unsupported url")));
+        let stream = self.wrap(url, &key);
+        self.connected.push_back((key.clone(), stream));
+        Ok(key)
+    }
+
+    fn connected(&mut self) -> Option<(Self::Key, io::Result<Self::Output>)> {
+        self.connected.pop_front()
+    }
+
+    fn register(&mut self, _: Registration) {}
+}
+
+#[test]
+fn resolve_overrides() {
+    let addr: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+    let resolver = Overrides::new(vec![("API.example.com".to_string(), addr)], Arc::new(SystemResolver));
+    assert_eq!(resolver.resolve("api.example.com", 3000).unwrap(), vec![addr]);
+
+    // other ports of an overridden host are looked up as usual
+    let resolver = Overrides::new(vec![("localhost".to_string(), "10.0.0.1:8080".parse().unwrap())], Arc::new(SystemResolver));
+    assert!(resolver.resolve("localhost", 80).unwrap().iter().all(|addr| addr.port() == 80));
+
+    let v6: SocketAddr = "[::1]:80".parse().unwrap();
+    assert_eq!(SystemResolver.resolve("[::1]", 80).unwrap(), vec![v6]);
+}
+
+#[test]
+fn resolving_connector_keeps_host() {
+    use proxy::Proxies;
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let hosts = vec![("api.example.com".to_string(), addr)];
+    let resolver = Arc::new(Overrides::new(hosts, Arc::new(SystemResolver)));
+    let dialer = Arc::new(Dialer::new(resolver, Proxies::new(), Duration::from_secs(5)));
+    let mut connector = ResolvingConnector::new(dialer.clone(), Tls::default());
+
+    let url = Url::parse(&format!("http://api.example.com:{}/users", addr.port())).unwrap();
+    dialer.dial(&url);
+    let key = connector.connect(&url).unwrap();
+    assert_eq!(key, ("http", "api.example.com".to_string(), addr.port()));
+    assert!(connector.connected().unwrap().1.is_ok());
+    listener.accept().unwrap();
+}
+
+#[test]
+fn resolving_connector_falls_through() {
+    use std::net::TcpListener;
+
+    struct Fixed(Vec<SocketAddr>);
+    impl Resolver for Fixed {
+        fn resolve(&self, _: &str, _: u16) -> io::Result<Vec<SocketAddr>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    // nothing listens on a port that was just given up
+    let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let resolver = Fixed(vec![refused, listener.local_addr().unwrap()]);
+
+    let stream = connect(&resolver, "api.example.com", 80, Duration::from_secs(5)).unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+}
@@ -1,14 +1,62 @@
+use breaker::{CircuitBreaker, CircuitState};
+use bulkhead::Bulkhead;
+use cache::CacheStore;
+use codec::{Codec, Codecs, Payload};
+use compression::{self, Encoding};
+use cookie::CookieJar;
 use error::Error;
+use hedge::{HedgePolicy, Hedging};
+use hosts::Balancing;
 use http;
-use serializer::ToJsonString;
-
+use multipart::{self, Form};
+use paginate::{self, Pagination};
+use patch::{self, JsonPatch, MergePatch};
+use problem::Problem;
+use proxy::{Proxy, Proxies};
+use ratelimit::{Quota, RateLimiter};
+use redirect::RedirectPolicy;
+use resolve::{Overrides, Resolver, SystemResolver};
+use serializer::{self, BodyEncoding};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Map, Value};
+use time::Tm;
+use tls::Tls;
+use url::form_urlencoded;
 use std::time::Duration;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub struct EndpointBuilder {
     url: String,
     timeout: Duration,
-    header: HashMap<String,String>
+    header: HashMap<String,String>,
+    redirect: RedirectPolicy,
+    decompress: bool,
+    max_body_size: Option<usize>,
+    codecs: Codecs,
+    cache: Option<Arc<CacheStore>>,
+    request_limit: Option<(usize, Duration)>,
+    breaker: Option<Arc<CircuitBreaker>>,
+    max_concurrency: Option<(usize, usize, Duration)>,
+    mirrors: Vec<(String, u32)>,
+    balancing: Balancing,
+    host_cooldown: Duration,
+    hedging: Option<(HedgePolicy, usize)>,
+    cookies: Option<Arc<CookieJar>>,
+    tls: Option<Tls>,
+    proxies: Proxies,
+    unix_socket: Option<PathBuf>,
+    resolve: Vec<(String, SocketAddr)>,
+    resolver: Option<Arc<Resolver>>
 }
 
 pub struct Endpoint {
@@ -24,21 +72,39 @@ pub struct GetBuilder {
 
 pub struct PostBuilder {
     path: String,
-    parameter: HashMap<String,String>,
-    header: HashMap<String,String>
+    parameter: Map<String,Value>,
+    header: HashMap<String,String>,
+    encoding: BodyEncoding,
+    body: Option<http::RequestBody>,
+    value: Option<Payload>,
+    form: Option<Form>,
+    idempotency_key: Option<String>,
+    compress: Option<Encoding>,
+    compress_threshold: usize
 }
 
 pub struct PutBuilder {
     path: String,
-    parameter: HashMap<String,String>,
-    header: HashMap<String,String>
+    parameter: Map<String,Value>,
+    header: HashMap<String,String>,
+    encoding: BodyEncoding,
+    body: Option<http::RequestBody>,
+    value: Option<Payload>,
+    compress: Option<Encoding>,
+    compress_threshold: usize
 }
 
 pub struct PatchBuilder {
     path: String,
     urlsegment: HashMap<String,String>,
-    parameter: HashMap<String,String>,
-    header: HashMap<String,String>
+    parameter: Map<String,Value>,
+    header: HashMap<String,String>,
+    encoding: BodyEncoding,
+    body: Option<http::RequestBody>,
+    value: Option<Payload>,
+    patch: Option<(Value, &'static str)>,
+    compress: Option<Encoding>,
+    compress_threshold: usize
 }
 
 pub struct DeleteBuilder {
@@ -52,6 +118,40 @@ pub struct Request {
     inner: http::Request
 }
 
+pub struct Response {
+    inner: http::Response,
+    codecs: Codecs,
+    stream: Option<http::Body>,
+    idempotency_key: Option<String>
+}
+
+/// A request body: bytes, a string or anything `Read`.
+pub struct Body {
+    inner: http::RequestBody
+}
+
+/// Iterator over the pages of a collection, see `Client::paginate`.
+pub struct Pages<'a> {
+    endpoint: &'a Endpoint,
+    pagination: Pagination,
+    items: Option<String>,
+    next: Option<http::Request>,
+    visited: HashSet<String>
+}
+
+/// Iterator over the decoded items of every page, see `Pages::items`.
+pub struct Items<'a, T> {
+    pages: Pages<'a>,
+    buffer: VecDeque<Value>,
+    item: PhantomData<T>
+}
+
+/// Iterator over the body of a `Response`, see `Response::chunks`.
+pub struct Chunks {
+    buffered: Option<Vec<u8>>,
+    stream: Option<http::Body>
+}
+
 pub struct Header {
     header: HashMap<String,String>
 }
@@ -91,22 +191,119 @@ impl Endpoint {
         EndpointBuilder {
             url: String::new(),
             timeout: Duration::from_secs(10),
-            header: HashMap::new()
+            header: HashMap::new(),
+            redirect: RedirectPolicy::None,
+            decompress: true,
+            max_body_size: None,
+            codecs: Codecs::new(),
+            cache: None,
+            request_limit: None,
+            breaker: None,
+            max_concurrency: None,
+            mirrors: Vec::new(),
+            balancing: Balancing::RoundRobin,
+            host_cooldown: Duration::from_secs(30),
+            hedging: None,
+            cookies: None,
+            tls: None,
+            proxies: Proxies::new(),
+            unix_socket: None,
+            resolve: Vec::new(),
+            resolver: None
         }
     }
 
     fn new(builder: &EndpointBuilder) -> Endpoint {
+        // a socket needs no base URL, but requests still need a host to name
+        let url = if builder.url.is_empty() && builder.unix_socket.is_some() {
+            "http://localhost/".to_string()
+        } else {
+            builder.url.clone()
+        };
+        let mut inner = http::Endpoint::new(url, builder.timeout.clone(), builder.header.clone());
+        inner.redirect = builder.redirect.clone();
+        inner.decompress = builder.decompress;
+        inner.max_body_size = builder.max_body_size;
+        inner.codecs = builder.codecs.clone();
+        inner.cache = builder.cache.clone();
+        inner.limiter = Arc::new(RateLimiter::new(builder.request_limit));
+        inner.breaker = builder.breaker.clone();
+        inner.cookies = builder.cookies.clone();
+        if let Some(ref tls) = builder.tls {
+            inner.set_tls(tls.clone());
+        }
+        if !builder.proxies.is_empty() {
+            inner.set_proxies(builder.proxies.clone());
+        }
+        if !builder.resolve.is_empty() || builder.resolver.is_some() {
+            let fallback = builder.resolver.clone().unwrap_or(Arc::new(SystemResolver));
+            inner.set_resolver(Arc::new(Overrides::new(builder.resolve.clone(), fallback)));
+        }
+        if let Some(ref path) = builder.unix_socket {
+            inner.set_unix_socket(path.clone());
+        }
+        inner.hedging = builder.hedging.clone().map(|(policy, max_attempts)| Arc::new(Hedging::new(policy, max_attempts)));
+        if !builder.mirrors.is_empty() {
+            inner.add_hosts(&builder.mirrors, builder.balancing, builder.host_cooldown);
+        }
+        inner.bulkhead = builder.max_concurrency.map(|(requests, queue_size, queue_timeout)| {
+            Arc::new(Bulkhead::new(requests, queue_size, queue_timeout))
+        });
         Endpoint {
-            inner: http::Endpoint::new(builder.url.clone(), builder.timeout.clone(), builder.header.clone()),
+            inner: inner,
         }
     }
 
+    /// The cookie jar, e.g. to `save` the session once done.
+    pub fn cookie_jar(&self) -> Option<&CookieJar> {
+        self.inner.cookies.as_ref().map(|jar| &**jar)
+    }
+
+    /// Requests currently holding a `max_concurrency` slot.
+    pub fn in_flight(&self) -> usize {
+        self.inner.bulkhead.as_ref().map_or(0, |bulkhead| bulkhead.in_flight())
+    }
+
+    /// Requests waiting for a `max_concurrency` slot.
+    pub fn queued(&self) -> usize {
+        self.inner.bulkhead.as_ref().map_or(0, |bulkhead| bulkhead.queued())
+    }
+
+    /// The state of the circuit breaker, if one is configured.
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.inner.breaker.as_ref().map(|breaker| breaker.state())
+    }
+
+    /// The quota the server last reported through its rate-limit headers.
+    pub fn quota(&self) -> Quota {
+        self.inner.limiter.quota()
+    }
+
 }
 
 impl EndpointBuilder
 {
+    /// The primary base URL, with a weight of 1 when balancing.
     pub fn url(&mut self, url: &str) -> &mut EndpointBuilder {
-        self.url.push_str(url);
+        self.url = url.to_string();
+        self
+    }
+
+    /// Another base URL serving the same API, see `balancing`.
+    pub fn add_url(&mut self, url: &str, weight: u32) -> &mut EndpointBuilder {
+        self.mirrors.push((url.to_string(), weight));
+        self
+    }
+
+    /// How requests are spread over the base URLs (round-robin by default).
+    pub fn balancing(&mut self, balancing: Balancing) -> &mut EndpointBuilder {
+        self.balancing = balancing;
+        self
+    }
+
+    /// How long a base URL that could not be reached is left out of rotation (30 seconds by default).
+    pub fn host_cooldown(&mut self, cooldown: Duration) -> &mut EndpointBuilder {
+        self.host_cooldown = cooldown;
         self
     }
 
@@ -115,7 +312,126 @@ impl EndpointBuilder
         self
     }
 
+    pub fn redirect(&mut self, policy: RedirectPolicy) -> &mut EndpointBuilder {
+        self.redirect = policy;
+        self
+    }
+
+    /// Negotiate and transparently decode gzip, deflate and brotli responses (on by default).
+    pub fn decompress(&mut self, decompress: bool) -> &mut EndpointBuilder {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Fail any response whose (decoded) body is larger than `bytes`.
+    pub fn max_body_size(&mut self, bytes: usize) -> &mut EndpointBuilder {
+        self.max_body_size = Some(bytes);
+        self
+    }
+
+    /// Encodes request bodies with `codec` and prefers it in `Accept`.
+    pub fn codec<C: Codec + 'static>(&mut self, codec: C) -> &mut EndpointBuilder {
+        self.codecs.select(Arc::new(codec));
+        self
+    }
+
+    /// Registers `codec` for decoding responses of its media type.
+    pub fn add_codec<C: Codec + 'static>(&mut self, codec: C) -> &mut EndpointBuilder {
+        self.codecs.add(Arc::new(codec));
+        self
+    }
+
+    /// Caches GET responses in `store` following `Cache-Control`, `Expires` and `Vary`.
+    pub fn cache<C: CacheStore + 'static>(&mut self, store: C) -> &mut EndpointBuilder {
+        self.cache = Some(Arc::new(store));
+        self
+    }
+
+    /// Custom root CAs, client certificates, pins or TLS versions, see `Tls::configure`.
+    pub fn tls(&mut self, tls: Tls) -> &mut EndpointBuilder {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Connects to `addr` for every request to `host` on `addr`'s port, like curl's
+    /// `--resolve host:port:address`. The `Host` header and TLS server name still use `host`.
+    pub fn resolve(&mut self, host: &str, addr: SocketAddr) -> &mut EndpointBuilder {
+        self.resolve.push((host.to_string(), addr));
+        self
+    }
+
+    /// Looks up host names with `resolver` instead of the system resolver;
+    /// hosts given to `resolve` are still overridden.
+    pub fn resolver<R: Resolver + 'static>(&mut self, resolver: R) -> &mut EndpointBuilder {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Talks to a local daemon over the Unix domain socket at `path`, e.g.
+    /// `/var/run/docker.sock`. The base URL defaults to `http://localhost/` and
+    /// only supplies the `Host` header and a path prefix; TLS and proxies don't apply.
+    pub fn unix_socket<P: AsRef<Path>>(&mut self, path: P) -> &mut EndpointBuilder {
+        self.unix_socket = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sends every request through the proxy at `url`, an `http://` proxy (forwarding
+    /// plain HTTP, tunnelling HTTPS with `CONNECT`) or a `socks5://` one, with optional `user:password@`.
+    /// Any other url fails with `Error::InvalidProxy`.
+    pub fn proxy(&mut self, url: &str) -> Result<&mut EndpointBuilder, Error> {
+        let proxy = try!(Proxy::parse(url).ok_or(Error::InvalidProxy(url.to_string())));
+        self.proxies.http = Some(proxy.clone());
+        self.proxies.https = Some(proxy);
+        Ok(self)
+    }
+
+    /// Uses the `http_proxy`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` environment variables.
+    pub fn proxy_from_env(&mut self) -> &mut EndpointBuilder {
+        let env = Proxies::from_env();
+        self.proxies.http = env.http.or(self.proxies.http.take());
+        self.proxies.https = env.https.or(self.proxies.https.take());
+        self.proxies.no_proxy.extend(env.no_proxy);
+        self
+    }
+
+    /// Hosts to reach directly, in `NO_PROXY` syntax: `localhost, .corp, 10.0.0.1, host:8080`.
+    pub fn no_proxy(&mut self, rules: &str) -> &mut EndpointBuilder {
+        self.proxies.no_proxy.extend(Proxies::rules(rules));
+        self
+    }
+
+    /// Keeps cookies set by the server in `jar` and sends them back, see `CookieJar::load`.
+    pub fn cookie_jar(&mut self, jar: CookieJar) -> &mut EndpointBuilder {
+        self.cookies = Some(Arc::new(jar));
+        self
+    }
+
+    /// Sends up to `max_attempts` copies of a slow GET as `policy` says, keeping
+    /// the first response. With several base URLs the copies are balanced like
+    /// any other request; with a cache the misses and revalidations are hedged.
+    pub fn hedge(&mut self, policy: HedgePolicy, max_attempts: usize) -> &mut EndpointBuilder {
+        self.hedging = Some((policy, max_attempts));
+        self
+    }
+
+    /// Allows at most `requests` requests in flight at once. Up to `queue_size`
+    /// more wait for a slot for at most `queue_timeout`; anything beyond that
+    /// fails with `Error::BulkheadFull`.
+    pub fn max_concurrency(&mut self, requests: usize, queue_size: usize, queue_timeout: Duration) -> &mut EndpointBuilder {
+        self.max_concurrency = Some((requests, queue_size, queue_timeout));
+        self
+    }
+
+    /// Fails requests fast with `Error::CircuitOpen` while `breaker` is open.
+    pub fn circuit_breaker(&mut self, breaker: CircuitBreaker) -> &mut EndpointBuilder {
+        self.breaker = Some(Arc::new(breaker));
+        self
+    }
+
+    /// Sends at most `requests` requests per `timeout`, on top of pacing to the
+    /// server's `RateLimit-*`, `X-RateLimit-*` and `Retry-After` headers.
     pub fn request_limit(&mut self, requests: i32, timeout: Duration) -> &mut EndpointBuilder {
+        self.request_limit = Some((::std::cmp::max(requests, 0) as usize, timeout));
         self
     }
     pub fn add_header(&mut self, header: &str, value: &str) -> &mut EndpointBuilder {
@@ -130,11 +446,383 @@ impl EndpointBuilder
 
 impl Client {
     pub fn execute(endpoint: &Endpoint, request: &Request) -> Result<String, Error> {
-        Ok(http::Client::request(&endpoint.inner, &request.inner))
+        try!(Client::send(endpoint, request)).text()
     }
 
     pub fn execute_headers(endpoint: &Endpoint, request: &Request, header: &Header) -> Result<String, Error> {
-        Ok(http::Client::request(&endpoint.inner, &request.inner)) //http:Header::new(header.headers)
+        Client::execute(endpoint, request) //http:Header::new(header.headers)
+    }
+
+    /// Sends `request`. 4xx and 5xx responses come back as `Error::Problem` when
+    /// they carry an `application/problem+json` body and as `Error::Status` otherwise.
+    pub fn send(endpoint: &Endpoint, request: &Request) -> Result<Response, Error> {
+        let response = try!(Client::exchange(endpoint, request));
+        match response.error() {
+            Some(err) => Err(err),
+            None => Ok(response)
+        }
+    }
+
+    /// Sends `request` and returns whatever status comes back.
+    fn exchange(endpoint: &Endpoint, request: &Request) -> Result<Response, Error> {
+        let inner = try!(http::Client::request(&endpoint.inner, &request.inner));
+        Ok(Response {
+            inner: inner,
+            codecs: endpoint.inner.codecs.clone(),
+            stream: None,
+            idempotency_key: request.inner.idempotency_key().cloned()
+        })
+    }
+
+    /// Read-modify-write guarded by `If-Match`: GETs `request`, hands the decoded
+    /// resource to `update` and PUTs the result back to the same route with the
+    /// `ETag` it was read with. A `412 Precondition Failed` starts over, up to
+    /// `retries` more times. The GET always revalidates with the server so a cached
+    /// copy cannot hand out an outdated `ETag`.
+    pub fn update_with_etag<T, F>(endpoint: &Endpoint, request: &Request, retries: usize, mut update: F) -> Result<Response, Error>
+        where T: Serialize + DeserializeOwned + Send + Sync + 'static, F: FnMut(T) -> T
+    {
+        let mut get = request.inner.clone();
+        get.set_header("Cache-Control", "no-cache");
+        let get = Request { inner: get };
+
+        for attempt in 0..(retries + 1) {
+            // an error status on the GET fails right away, only the PUT's 412 starts over
+            let current = try!(Client::send(endpoint, &get));
+            let etag = match current.header("ETag") {
+                Some(etag) => etag,
+                None => return Err(Error::MissingETag)
+            };
+            let updated = update(try!(current.decode()));
+
+            // the GET's query string selected the resource, the PUT addresses it by path like `put()`
+            let route = request.inner.route().map(|route| route.split('?').next().unwrap_or("").to_string());
+            let mut put = http::Request::new(http::Method::Put, route, None);
+            for (name, value) in request.inner.headers().iter() {
+                put.set_header(name, value);
+            }
+            put.set_value(Payload::new(updated));
+            put.set_header("If-Match", &etag);
+
+            let response = try!(Client::exchange(endpoint, &Request { inner: put }));
+            if response.status() != 412 {
+                return match response.error() {
+                    Some(err) => Err(err),
+                    None => Ok(response)
+                };
+            }
+            debug!("{} changed underneath us, attempt {} of {}", current.url(), attempt + 1, retries + 1);
+        }
+        Err(Error::PreconditionFailed(retries + 1))
+    }
+
+    /// Walks a paginated collection starting at `request`, one request per page.
+    /// Paging stops after an error, which is yielded like `send` returns it, an
+    /// empty page or when `pagination` finds no next page.
+    pub fn paginate<'a>(endpoint: &'a Endpoint, request: &Request, pagination: Pagination) -> Pages<'a> {
+        let mut first = request.inner.clone();
+        first.set_route(pagination.first_route(request.inner.route().map(|r| r.as_str()).unwrap_or("")));
+        Pages {
+            endpoint: endpoint,
+            pagination: pagination,
+            items: None,
+            next: Some(first),
+            visited: HashSet::new()
+        }
+    }
+
+    /// Returns as soon as the response head arrives; the body is read lazily through `Response::chunks`.
+    /// Error statuses are reported like `send` does, after reading their body.
+    pub fn stream(endpoint: &Endpoint, request: &Request) -> Result<Response, Error> {
+        let (inner, body) = try!(http::Client::stream(&endpoint.inner, &request.inner));
+        let mut response = Response {
+            inner: inner,
+            codecs: endpoint.inner.codecs.clone(),
+            stream: Some(body),
+            idempotency_key: request.inner.idempotency_key().cloned()
+        };
+        if response.is_error() {
+            let mut body = Vec::new();
+            for chunk in (Chunks { buffered: None, stream: response.stream.take() }) {
+                body.extend(try!(chunk));
+            }
+            response.inner.body = Some(body);
+        }
+        match response.error() {
+            Some(err) => Err(err),
+            None => Ok(response)
+        }
+    }
+
+    /// Copies the response body into `writer` chunk by chunk and returns the response head.
+    pub fn download<W: Write>(endpoint: &Endpoint, request: &Request, writer: &mut W) -> Result<Response, Error> {
+        let mut response = try!(Client::stream(endpoint, request));
+        let chunks = Chunks {
+            buffered: None,
+            stream: response.stream.take()
+        };
+        for chunk in chunks {
+            try!(writer.write_all(&try!(chunk)));
+        }
+        Ok(response)
+    }
+}
+
+/// A clone of a response from `Client::stream` has the head only, the body stays with the original.
+impl Clone for Response {
+    fn clone(&self) -> Response {
+        Response {
+            inner: self.inner.clone(),
+            codecs: self.codecs.clone(),
+            stream: None,
+            idempotency_key: self.idempotency_key.clone()
+        }
+    }
+}
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("inner", &self.inner)
+            .field("streaming", &self.stream.is_some())
+            .field("idempotency_key", &self.idempotency_key)
+            .finish()
+    }
+}
+
+impl Response {
+    pub fn status(&self) -> u16 {
+        self.inner.status.to_u16()
+    }
+
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.inner.headers.get_raw(name)
+            .and_then(|values| values.first())
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+    }
+
+    /// The URL the response was served from, after any redirects.
+    pub fn url(&self) -> String {
+        self.inner.url.to_string()
+    }
+
+    /// The `Idempotency-Key` the request was sent with, see `PostBuilder::idempotent`.
+    pub fn idempotency_key(&self) -> Option<&str> {
+        self.idempotency_key.as_ref().map(|key| key.as_str())
+    }
+
+    /// Every URL that redirected on the way to `url`, oldest first.
+    pub fn redirects(&self) -> Vec<String> {
+        self.inner.redirects.iter().map(|url| url.to_string()).collect()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        match self.inner.body {
+            Some(ref body) => body,
+            None => &[]
+        }
+    }
+
+    pub fn text(&self) -> Result<String, Error> {
+        Ok(try!(String::from_utf8(self.body().to_vec())))
+    }
+
+    /// Decodes the body with the codec matching the response `Content-Type`.
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let content_type = self.header("Content-Type");
+        self.codecs.decode(content_type.as_ref().map(|c| c.as_str()), self.body())
+    }
+
+    fn is_error(&self) -> bool {
+        self.inner.status.is_client_error() || self.inner.status.is_server_error()
+    }
+
+    /// The error `send` reports for a 4xx or 5xx response.
+    fn error(&self) -> Option<Error> {
+        if !self.is_error() {
+            return None;
+        }
+        let content_type = self.header("Content-Type");
+        Some(match Problem::parse(content_type.as_ref().map(|c| c.as_str()), self.body()) {
+            Some(problem) => Error::Problem(problem),
+            None => Error::Status(self.status(), String::from_utf8_lossy(self.body()).into_owned())
+        })
+    }
+
+    /// The body in the pieces it arrived in. For responses from `Client::stream`
+    /// this is the only way to get at the body; `body` and `text` stay empty.
+    pub fn chunks(self) -> Chunks {
+        Chunks {
+            buffered: self.inner.body,
+            stream: self.stream
+        }
+    }
+}
+
+impl<'a> Pages<'a> {
+    /// Where the items of a page live in the body, as a dotted path like `data`.
+    /// By default the body itself is expected to be the array.
+    pub fn items_at(mut self, field: &str) -> Pages<'a> {
+        self.items = Some(field.to_string());
+        self
+    }
+
+    /// Flattens the pages into their items, decoded as `T`.
+    pub fn items<T: DeserializeOwned>(self) -> Items<'a, T> {
+        Items {
+            pages: self,
+            buffer: VecDeque::new(),
+            item: PhantomData
+        }
+    }
+
+    fn next_page(&mut self) -> Option<Result<(Response, Option<Vec<Value>>), Error>> {
+        let request = try_opt!(self.next.take());
+        let response = match Client::send(self.endpoint, &Request { inner: request.clone() }) {
+            Ok(response) => response,
+            Err(e) => return Some(Err(e))
+        };
+
+        let body = response.decode::<Value>().ok();
+        let items = body.as_ref()
+            .and_then(|body| match self.items {
+                Some(ref field) => paginate::lookup(body, field),
+                None => Some(body)
+            })
+            .and_then(|items| items.as_array().cloned());
+        match items {
+            Some(ref items) if items.is_empty() => return None,
+            None if self.pagination.needs_items() => return Some(Ok((response, None))),
+            _ => {}
+        }
+
+        let route = request.route().map(|r| r.as_str()).unwrap_or("");
+        self.visited.insert(route.to_string());
+        self.visited.insert(response.url());
+        let link = response.header("Link");
+        let next = self.pagination.next_route(route, link.as_ref().map(|l| l.as_str()), body.as_ref());
+        // `Link` targets are relative to the page they came from, not to the endpoint
+        let next = match self.pagination {
+            Pagination::Link => next.and_then(|target| response.inner.url.join(&target).ok()).map(|url| url.to_string()),
+            _ => next
+        };
+        match next {
+            // a server that hands out a page seen before would never let paging end
+            Some(ref next) if self.visited.contains(next) => {
+                debug!("{} points back at {}, stopping", response.url(), next);
+            },
+            Some(next) => {
+                let mut request = request.clone();
+                request.set_route(next);
+                self.next = Some(request);
+            },
+            None => {}
+        }
+        Some(Ok((response, items)))
+    }
+}
+
+impl<'a> Iterator for Pages<'a> {
+    type Item = Result<Response, Error>;
+
+    fn next(&mut self) -> Option<Result<Response, Error>> {
+        self.next_page().map(|page| page.map(|(response, _)| response))
+    }
+}
+
+impl<'a, T: DeserializeOwned> Iterator for Items<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        loop {
+            if let Some(item) = self.buffer.pop_front() {
+                return Some(serde_json::from_value(item).map_err(|e| Error::Codec(e.to_string())));
+            }
+            match try_opt!(self.pages.next_page()) {
+                Ok((_, Some(items))) => self.buffer.extend(items),
+                Ok((response, None)) => {
+                    return Some(Err(Error::Codec(format!("{} returned {} without a list of items", response.url(), response.status()))));
+                },
+                Err(e) => return Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Body {
+    /// Streams `reader` with `Transfer-Encoding: chunked`. A reader can only be
+    /// sent once; a redirect or retry that needs it again fails with
+    /// `Error::BodyNotReplayable`, see `Body::source` for bodies that can be resent.
+    pub fn reader<R: Read + Send + 'static>(reader: R) -> Body {
+        Body {
+            inner: http::RequestBody::from_reader(reader, None)
+        }
+    }
+
+    /// Streams a fresh reader from `open` for every send, with a `Content-Length` if `len` is given.
+    pub fn source<F>(open: F, len: Option<u64>) -> Body
+        where F: Fn() -> io::Result<Box<Read + Send>> + Send + Sync + 'static
+    {
+        Body {
+            inner: http::RequestBody::from_source(open, len)
+        }
+    }
+
+    /// Streams `reader` with a `Content-Length` of `len`.
+    pub fn sized_reader<R: Read + Send + 'static>(reader: R, len: u64) -> Body {
+        Body {
+            inner: http::RequestBody::from_reader(reader, Some(len))
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body {
+            inner: http::RequestBody::Bytes(bytes)
+        }
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Body {
+        Body::from(text.into_bytes())
+    }
+}
+
+impl<'a> From<&'a Path> for Body {
+    fn from(path: &'a Path) -> Body {
+        Body::from(path.to_path_buf())
+    }
+}
+
+impl From<PathBuf> for Body {
+    fn from(path: PathBuf) -> Body {
+        Body {
+            inner: multipart::file(path)
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Body {
+    fn from(text: &'a str) -> Body {
+        Body::from(text.as_bytes().to_vec())
+    }
+}
+
+impl Iterator for Chunks {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>, Error>> {
+        if let Some(body) = self.buffered.take() {
+            if !body.is_empty() {
+                return Some(Ok(body));
+            }
+        }
+        match self.stream {
+            Some(ref mut stream) => stream.next(),
+            None => None
+        }
     }
 }
 
@@ -156,6 +844,7 @@ impl GetBuilder {
     }
 
     pub fn add_header(&mut self, header: &str, value: &str)  -> &mut GetBuilder {
+        self.header.entry(header.to_string()).or_insert(value.to_string());
         self
     }
 
@@ -182,18 +871,23 @@ impl GetBuilder {
         }
 
         if self.parameter.len() >= 1 {
+            let mut parameters: Vec<_> = self.parameter.iter().collect();
+            parameters.sort();
             route.push_str("?");
-            for(key, val) in self.parameter.iter() {
-                let format_query = String::new() + key + "=" + val;
-                route.push_str(&format_query);
-            }
+            route.push_str(&form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(parameters)
+                .finish());
         }
         route
     }
 
     pub fn build(&self) -> Request {
+        let mut inner = http::Request::new(http::Method::Get, Some(self.parse_route()), None);
+        for (name, value) in self.header.iter() {
+            inner.set_header(name, value);
+        }
         Request {
-            inner: http::Request::new(http::Method::Get, Some(self.parse_route()), None),
+            inner: inner,
         }
     }
 }
@@ -203,8 +897,15 @@ impl PostBuilder {
     {
         PostBuilder {
             path: String::new(),
-            parameter: HashMap::new(),
-            header: HashMap::new()
+            parameter: Map::new(),
+            header: HashMap::new(),
+            encoding: BodyEncoding::Json,
+            body: None,
+            value: None,
+            form: None,
+            idempotency_key: None,
+            compress: None,
+            compress_threshold: compression::DEFAULT_THRESHOLD
         }
     }
 
@@ -214,21 +915,137 @@ impl PostBuilder {
     }
 
     pub fn add_header(&mut self, header: &str, value: &str)  -> &mut PostBuilder {
+        self.header.entry(header.to_string()).or_insert(value.to_string());
         self
     }
 
-    pub fn add_parameter(&mut self, parameter: &str, value: &str) -> &mut PostBuilder {
-        self.parameter.entry(parameter.to_string()).or_insert(value.to_string());
+    pub fn add_parameter<V: Into<Value>>(&mut self, parameter: &str, value: V) -> &mut PostBuilder {
+        self.parameter.entry(parameter.to_string()).or_insert(value.into());
+        self
+    }
+
+    /// Sets a nested body field, e.g. `add_json_path("address.city", "X")`.
+    /// Like `add_parameter`, the first value set for a field is kept.
+    pub fn add_json_path<V: Into<Value>>(&mut self, path: &str, value: V) -> &mut PostBuilder {
+        serializer::insert_path(&mut self.parameter, path, value.into());
+        self
+    }
+
+    /// Sends `value` as the whole body, ignoring any parameters.
+    pub fn body_json(&mut self, value: Value) -> &mut PostBuilder {
+        self.value = Some(Payload::new(value));
+        self
+    }
+
+    /// Chooses how `add_parameter` entries are serialized, JSON by default.
+    pub fn body_encoding(&mut self, encoding: BodyEncoding) -> &mut PostBuilder {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sends the parameters as `application/x-www-form-urlencoded`.
+    pub fn form(&mut self) -> &mut PostBuilder {
+        self.body_encoding(BodyEncoding::Form)
+    }
+
+    /// Sends `body` as is instead of the encoded parameters.
+    pub fn body<B: Into<Body>>(&mut self, body: B) -> &mut PostBuilder {
+        self.body = Some(body.into().inner);
+        self
+    }
+
+    /// Sends a fresh `Idempotency-Key`. The built request keeps it, so resending
+    /// it (and failing it over to another base URL) is safe with APIs that honor it.
+    pub fn idempotent(&mut self) -> &mut PostBuilder {
+        if self.idempotency_key.is_none() {
+            self.idempotency_key = Some(http::idempotency_key());
+        }
+        self
+    }
+
+    /// Like `idempotent`, with a key of your own, e.g. one stored with the job it belongs to.
+    pub fn idempotency_key(&mut self, key: &str) -> &mut PostBuilder {
+        self.idempotency_key = Some(key.to_string());
+        self
+    }
+
+    /// Sends a `multipart/form-data` body built from `add_text`, `add_file` and `add_part`.
+    pub fn multipart(&mut self) -> &mut PostBuilder {
+        if self.form.is_none() {
+            self.form = Some(Form::new());
+        }
+        self
+    }
+
+    pub fn add_text(&mut self, name: &str, value: &str) -> &mut PostBuilder {
+        self.multipart();
+        if let Some(ref mut form) = self.form {
+            form.add_text(name, value);
+        }
+        self
+    }
+
+    /// Adds a file part read from `path`, which is opened lazily when the request is sent.
+    pub fn add_file<P: AsRef<Path>>(&mut self, name: &str, path: P, filename: &str, mime: &str) -> &mut PostBuilder {
+        self.add_part(name, path.as_ref(), filename, mime)
+    }
+
+    /// Adds a file part with contents from memory or a `Body::reader`.
+    pub fn add_part<B: Into<Body>>(&mut self, name: &str, body: B, filename: &str, mime: &str) -> &mut PostBuilder {
+        self.multipart();
+        if let Some(ref mut form) = self.form {
+            form.add_file(name, body.into().inner, filename, mime);
+        }
+        self
+    }
+
+    /// Sends `value` encoded with the endpoint's codec when the request goes out.
+    pub fn serialize<T: Serialize + Send + Sync + 'static>(&mut self, value: T) -> &mut PostBuilder {
+        self.value = Some(Payload::new(value));
+        self
+    }
+
+    pub fn compress(&mut self, encoding: Encoding) -> &mut PostBuilder {
+        self.compress = Some(encoding);
+        self
+    }
+
+    /// Bodies smaller than `bytes` are sent uncompressed.
+    pub fn compress_threshold(&mut self, bytes: usize) -> &mut PostBuilder {
+        self.compress_threshold = bytes;
         self
     }
 
     fn get_body(&self) -> Option<String> {
-         Some(self.parameter.tojson())
+         Some(self.encoding.encode(&self.parameter))
     }
 
     pub fn build(&self) -> Request {
+        let mut inner = http::Request::new(http::Method::Post, Some(self.path.clone()), None);
+        if let Some(ref body) = self.body {
+            inner.set_body(body.clone());
+        } else if let Some(ref value) = self.value {
+            inner.set_value(value.clone());
+        } else if let Some(ref form) = self.form {
+            inner.set_body(form.body());
+            inner.set_header("Content-Type", &form.content_type());
+        } else {
+            if let Some(body) = self.get_body() {
+                inner.set_body(http::RequestBody::Bytes(body.into_bytes()));
+            }
+            inner.set_header("Content-Type", self.encoding.content_type());
+        }
+        if let Some(encoding) = self.compress {
+            inner.compress(encoding, self.compress_threshold);
+        }
+        for (name, value) in self.header.iter() {
+            inner.set_header(name, value);
+        }
+        if let Some(ref key) = self.idempotency_key {
+            inner.set_header(http::IDEMPOTENCY_KEY, key);
+        }
         Request {
-            inner: http::Request::new(http::Method::Post, Some("posts".to_string()), self.get_body()),
+            inner: inner,
         }
     }
 }
@@ -238,8 +1055,13 @@ impl PutBuilder {
     {
         PutBuilder {
             path: String::new(),
-            parameter: HashMap::new(),
-            header: HashMap::new()
+            parameter: Map::new(),
+            header: HashMap::new(),
+            encoding: BodyEncoding::Json,
+            body: None,
+            value: None,
+            compress: None,
+            compress_threshold: compression::DEFAULT_THRESHOLD
         }
     }
 
@@ -249,17 +1071,98 @@ impl PutBuilder {
     }
 
     pub fn add_header(&mut self, header: &str, value: &str)  -> &mut PutBuilder {
+        self.header.entry(header.to_string()).or_insert(value.to_string());
         self
     }
 
-    pub fn add_parameter(&mut self, parameter: &str, value: &str) -> &mut PutBuilder {
-        self.parameter.entry(parameter.to_string()).or_insert(value.to_string());
+    /// Only apply the change if the resource still has this `ETag`.
+    pub fn if_match(&mut self, etag: &str) -> &mut PutBuilder {
+        self.header.insert("If-Match".to_string(), etag.to_string());
         self
     }
 
+    /// Only apply the change if the resource was not modified after `time`.
+    pub fn if_unmodified_since(&mut self, time: Tm) -> &mut PutBuilder {
+        self.header.insert("If-Unmodified-Since".to_string(), time.to_utc().rfc822().to_string());
+        self
+    }
+
+    pub fn add_parameter<V: Into<Value>>(&mut self, parameter: &str, value: V) -> &mut PutBuilder {
+        self.parameter.entry(parameter.to_string()).or_insert(value.into());
+        self
+    }
+
+    /// Sets a nested body field, e.g. `add_json_path("address.city", "X")`.
+    /// Like `add_parameter`, the first value set for a field is kept.
+    pub fn add_json_path<V: Into<Value>>(&mut self, path: &str, value: V) -> &mut PutBuilder {
+        serializer::insert_path(&mut self.parameter, path, value.into());
+        self
+    }
+
+    /// Sends `value` as the whole body, ignoring any parameters.
+    pub fn body_json(&mut self, value: Value) -> &mut PutBuilder {
+        self.value = Some(Payload::new(value));
+        self
+    }
+
+    /// Chooses how `add_parameter` entries are serialized, JSON by default.
+    pub fn body_encoding(&mut self, encoding: BodyEncoding) -> &mut PutBuilder {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sends the parameters as `application/x-www-form-urlencoded`.
+    pub fn form(&mut self) -> &mut PutBuilder {
+        self.body_encoding(BodyEncoding::Form)
+    }
+
+    /// Sends `body` as is instead of the encoded parameters.
+    pub fn body<B: Into<Body>>(&mut self, body: B) -> &mut PutBuilder {
+        self.body = Some(body.into().inner);
+        self
+    }
+
+    /// Sends `value` encoded with the endpoint's codec when the request goes out.
+    pub fn serialize<T: Serialize + Send + Sync + 'static>(&mut self, value: T) -> &mut PutBuilder {
+        self.value = Some(Payload::new(value));
+        self
+    }
+
+    pub fn compress(&mut self, encoding: Encoding) -> &mut PutBuilder {
+        self.compress = Some(encoding);
+        self
+    }
+
+    /// Bodies smaller than `bytes` are sent uncompressed.
+    pub fn compress_threshold(&mut self, bytes: usize) -> &mut PutBuilder {
+        self.compress_threshold = bytes;
+        self
+    }
+
+    fn get_body(&self) -> Option<String> {
+         Some(self.encoding.encode(&self.parameter))
+    }
+
     pub fn build(&self) -> Request {
+        let mut inner = http::Request::new(http::Method::Put, Some(self.path.clone()), None);
+        if let Some(ref body) = self.body {
+            inner.set_body(body.clone());
+        } else if let Some(ref value) = self.value {
+            inner.set_value(value.clone());
+        } else {
+            if let Some(body) = self.get_body() {
+                inner.set_body(http::RequestBody::Bytes(body.into_bytes()));
+            }
+            inner.set_header("Content-Type", self.encoding.content_type());
+        }
+        if let Some(encoding) = self.compress {
+            inner.compress(encoding, self.compress_threshold);
+        }
+        for (name, value) in self.header.iter() {
+            inner.set_header(name, value);
+        }
         Request {
-            inner: http::Request::new(http::Method::Put, None, None),
+            inner: inner,
         }
     }
 }
@@ -270,8 +1173,14 @@ impl PatchBuilder {
         PatchBuilder {
             path: String::new(),
             urlsegment: HashMap::new(),
-            parameter: HashMap::new(),
-            header: HashMap::new()
+            parameter: Map::new(),
+            header: HashMap::new(),
+            encoding: BodyEncoding::Json,
+            body: None,
+            value: None,
+            patch: None,
+            compress: None,
+            compress_threshold: compression::DEFAULT_THRESHOLD
         }
     }
 
@@ -281,6 +1190,19 @@ impl PatchBuilder {
     }
 
     pub fn add_header(&mut self, header: &str, value: &str)  -> &mut PatchBuilder {
+        self.header.entry(header.to_string()).or_insert(value.to_string());
+        self
+    }
+
+    /// Only apply the change if the resource still has this `ETag`.
+    pub fn if_match(&mut self, etag: &str) -> &mut PatchBuilder {
+        self.header.insert("If-Match".to_string(), etag.to_string());
+        self
+    }
+
+    /// Only apply the change if the resource was not modified after `time`.
+    pub fn if_unmodified_since(&mut self, time: Tm) -> &mut PatchBuilder {
+        self.header.insert("If-Unmodified-Since".to_string(), time.to_utc().rfc822().to_string());
         self
     }
 
@@ -289,14 +1211,107 @@ impl PatchBuilder {
         self
     }
 
-    pub fn add_parameter(&mut self, parameter: &str, value: &str) -> &mut PatchBuilder {
-        self.parameter.entry(parameter.to_string()).or_insert(value.to_string());
+    pub fn add_parameter<V: Into<Value>>(&mut self, parameter: &str, value: V) -> &mut PatchBuilder {
+        self.parameter.entry(parameter.to_string()).or_insert(value.into());
         self
     }
 
+    /// Sets a nested body field, e.g. `add_json_path("address.city", "X")`.
+    /// Like `add_parameter`, the first value set for a field is kept.
+    pub fn add_json_path<V: Into<Value>>(&mut self, path: &str, value: V) -> &mut PatchBuilder {
+        serializer::insert_path(&mut self.parameter, path, value.into());
+        self
+    }
+
+    /// Sends `value` as the whole body, ignoring any parameters.
+    pub fn body_json(&mut self, value: Value) -> &mut PatchBuilder {
+        self.value = Some(Payload::new(value));
+        self
+    }
+
+    /// Chooses how `add_parameter` entries are serialized, JSON by default.
+    pub fn body_encoding(&mut self, encoding: BodyEncoding) -> &mut PatchBuilder {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sends the parameters as `application/x-www-form-urlencoded`.
+    pub fn form(&mut self) -> &mut PatchBuilder {
+        self.body_encoding(BodyEncoding::Form)
+    }
+
+    /// Sends `body` as is instead of the encoded parameters.
+    pub fn body<B: Into<Body>>(&mut self, body: B) -> &mut PatchBuilder {
+        self.body = Some(body.into().inner);
+        self
+    }
+
+    /// Sends `value` encoded with the endpoint's codec when the request goes out.
+    pub fn serialize<T: Serialize + Send + Sync + 'static>(&mut self, value: T) -> &mut PatchBuilder {
+        self.value = Some(Payload::new(value));
+        self
+    }
+
+    /// Sends an RFC 6902 JSON Patch as `application/json-patch+json`.
+    pub fn json_patch(&mut self, patch: &JsonPatch) -> &mut PatchBuilder {
+        self.patch = Some((patch.to_value(), patch::JSON_PATCH));
+        self
+    }
+
+    /// Sends an RFC 7396 JSON Merge Patch as `application/merge-patch+json`.
+    pub fn merge_patch(&mut self, patch: &MergePatch) -> &mut PatchBuilder {
+        self.patch = Some((patch.to_value(), patch::MERGE_PATCH));
+        self
+    }
+
+    pub fn compress(&mut self, encoding: Encoding) -> &mut PatchBuilder {
+        self.compress = Some(encoding);
+        self
+    }
+
+    /// Bodies smaller than `bytes` are sent uncompressed.
+    pub fn compress_threshold(&mut self, bytes: usize) -> &mut PatchBuilder {
+        self.compress_threshold = bytes;
+        self
+    }
+
+    fn parse_route(&self) -> String {
+        let mut route = self.path.to_owned();
+
+        for (key, val) in self.urlsegment.iter() {
+            let format_key = String::new() + "{" + key + "}";
+            route = route.replace(&format_key, val);
+        }
+        route
+    }
+
+    fn get_body(&self) -> Option<String> {
+         Some(self.encoding.encode(&self.parameter))
+    }
+
     pub fn build(&self) -> Request {
+        let mut inner = http::Request::new(http::Method::Patch, Some(self.parse_route()), None);
+        if let Some(ref body) = self.body {
+            inner.set_body(body.clone());
+        } else if let Some((ref document, content_type)) = self.patch {
+            inner.set_body(http::RequestBody::Bytes(document.to_string().into_bytes()));
+            inner.set_header("Content-Type", content_type);
+        } else if let Some(ref value) = self.value {
+            inner.set_value(value.clone());
+        } else {
+            if let Some(body) = self.get_body() {
+                inner.set_body(http::RequestBody::Bytes(body.into_bytes()));
+            }
+            inner.set_header("Content-Type", self.encoding.content_type());
+        }
+        if let Some(encoding) = self.compress {
+            inner.compress(encoding, self.compress_threshold);
+        }
+        for (name, value) in self.header.iter() {
+            inner.set_header(name, value);
+        }
         Request {
-            inner: http::Request::new(http::Method::Patch, None, None),
+            inner: inner,
         }
     }
 }
@@ -317,6 +1332,19 @@ impl DeleteBuilder {
     }
 
     pub fn add_header(&mut self, header: &str, value: &str)  -> &mut DeleteBuilder {
+        self.header.entry(header.to_string()).or_insert(value.to_string());
+        self
+    }
+
+    /// Only apply the change if the resource still has this `ETag`.
+    pub fn if_match(&mut self, etag: &str) -> &mut DeleteBuilder {
+        self.header.insert("If-Match".to_string(), etag.to_string());
+        self
+    }
+
+    /// Only apply the change if the resource was not modified after `time`.
+    pub fn if_unmodified_since(&mut self, time: Tm) -> &mut DeleteBuilder {
+        self.header.insert("If-Unmodified-Since".to_string(), time.to_utc().rfc822().to_string());
         self
     }
 
@@ -325,9 +1353,23 @@ impl DeleteBuilder {
         self
     }
 
+    fn parse_route(&self) -> String {
+        let mut route = self.path.to_owned();
+
+        for (key, val) in self.urlsegment.iter() {
+            let format_key = String::new() + "{" + key + "}";
+            route = route.replace(&format_key, val);
+        }
+        route
+    }
+
     pub fn build(&self) -> Request {
+        let mut inner = http::Request::new(http::Method::Delete, Some(self.parse_route()), None);
+        for (name, value) in self.header.iter() {
+            inner.set_header(name, value);
+        }
         Request {
-            inner: http::Request::new(http::Method::Delete, None, None),
+            inner: inner,
         }
     }
 }
@@ -441,6 +1483,16 @@ fn get_builder_url_segment_paramter() {
     assert_eq!(&builder.parse_route(), "posts/1?userId=1");
 }
 
+#[test]
+fn get_builder_parameters() {
+    let mut builder = Request::get();
+    builder.path("posts");
+    builder.add_parameter("userId", "1");
+    builder.add_parameter("q", "a b&c");
+
+    assert_eq!(&builder.parse_route(), "posts?q=a+b%26c&userId=1");
+}
+
 #[test]
 fn post_builder_parameter() {
     let mut builder = Request::post();
@@ -456,3 +1508,114 @@ fn post_builder_parameter() {
     println!("{}", x);
     //assert_eq!(&builder.get_body(), "posts?userId=1");
 }
+
+#[test]
+fn post_builder_compress_threshold() {
+    let mut builder = Request::post();
+    builder.path("posts");
+    builder.add_parameter("title", "foo");
+    builder.compress(Encoding::Gzip);
+
+    //Small bodies are left alone
+    let request = builder.build();
+    assert_eq!(request.inner.header("Content-Encoding"), None);
+
+    //Anything over the threshold gets compressed
+    builder.compress_threshold(0);
+    let request = builder.build();
+    assert_eq!(request.inner.header("Content-Encoding"), Some(&"gzip".to_string()));
+}
+
+#[test]
+fn post_builder_idempotent() {
+    let mut builder = Request::post();
+    builder.path("payments").idempotent();
+
+    let first = builder.build();
+    let key = first.inner.idempotency_key().cloned().unwrap();
+    assert_eq!(key.len(), 36);
+    assert_eq!(builder.build().inner.idempotency_key(), Some(&key));
+
+    builder.idempotency_key("order-42");
+    assert_eq!(builder.build().inner.header("Idempotency-Key"), Some(&"order-42".to_string()));
+}
+
+#[test]
+fn put_builder_body_reader() {
+    use std::io::Cursor;
+
+    let mut builder = Request::put();
+    builder.path("uploads");
+
+    builder.body("raw");
+    assert_eq!(builder.build().inner.body_len(), Some(3));
+
+    //Unsized readers are sent chunked
+    builder.body(Body::reader(Cursor::new(vec![0; 16])));
+    assert_eq!(builder.build().inner.body_len(), None);
+}
+
+#[test]
+fn post_builder_form() {
+    let mut builder = Request::post();
+    builder.path("posts");
+    builder.add_parameter("title", "foo");
+
+    //JSON is the default
+    assert_eq!(builder.build().inner.header("Content-Type"), Some(&"application/json".to_string()));
+
+    builder.form();
+    assert_eq!(builder.get_body(), Some("title=foo".to_string()));
+    assert_eq!(builder.build().inner.header("Content-Type"), Some(&"application/x-www-form-urlencoded".to_string()));
+}
+
+#[test]
+fn post_builder_typed_parameter() {
+    let mut builder = Request::post();
+    builder.path("posts");
+    builder.add_parameter("userId", 1);
+    builder.add_parameter("draft", true);
+    builder.add_json_path("author.name", "foo");
+
+    assert_eq!(builder.get_body().unwrap(), r#"{"author":{"name":"foo"},"draft":true,"userId":1}"#);
+}
+
+#[test]
+fn patch_builder_json_patch() {
+    let mut patch = JsonPatch::new();
+    patch.test("/id", 1).replace("/title", "foo");
+
+    let mut builder = Request::patch();
+    builder.path("posts/{id}");
+    builder.add_urlsegment("id", "1");
+    builder.json_patch(&patch);
+
+    let request = builder.build();
+    assert_eq!(request.inner.header("Content-Type"), Some(&"application/json-patch+json".to_string()));
+}
+
+#[test]
+fn put_builder_if_match() {
+    let mut builder = Request::put();
+    builder.path("posts/1");
+    builder.if_match("\"v1\"");
+
+    let request = builder.build();
+    assert_eq!(request.inner.header("If-Match"), Some(&"\"v1\"".to_string()));
+}
+
+#[test]
+fn body_reader_not_replayable() {
+    use std::io::Cursor;
+
+    let body = Body::reader(Cursor::new(vec![0; 16]));
+    assert!(!body.inner.replayable());
+    assert!(body.inner.open().is_ok());
+    match body.inner.open() {
+        Err(Error::BodyNotReplayable) => {},
+        other => panic!("expected BodyNotReplayable, got {:?}", other.map(|_| ()))
+    }
+
+    let body = Body::from("raw");
+    assert!(body.inner.open().is_ok() && body.inner.open().is_ok());
+}
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use serde_json::value::{ToJson};
+use serde_json::{Map, Value};
+use url::form_urlencoded;
 
 
 pub trait ToJsonString {
@@ -7,9 +7,104 @@ pub trait ToJsonString {
     fn tojson(&self) -> String;
 }
 
-impl ToJsonString for HashMap<String,String>
+pub trait ToFormString {
+    /// Converts the value of `self` to an `application/x-www-form-urlencoded` string
+    fn toform(&self) -> String;
+}
+
+/// How a builder serializes its parameters into the request body.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyEncoding
+{
+    Json,
+    Form
+}
+
+impl ToJsonString for Map<String,Value>
 {
     fn tojson(&self) -> String {
-        self.to_json().to_string()
+        Value::Object(self.clone()).to_string()
+    }
+}
+
+impl ToFormString for Map<String,Value>
+{
+    /// Scalars are written as is, arrays and objects as their JSON text.
+    fn toform(&self) -> String {
+        let mut pairs: Vec<(&String, String)> = self.iter()
+            .map(|(key, value)| (key, form_value(value)))
+            .collect();
+        pairs.sort();
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish()
+    }
+}
+
+impl BodyEncoding {
+    pub fn content_type(&self) -> &'static str {
+        match *self {
+            BodyEncoding::Json => "application/json",
+            BodyEncoding::Form => "application/x-www-form-urlencoded"
+        }
+    }
+
+    pub fn encode(&self, parameters: &Map<String,Value>) -> String {
+        match *self {
+            BodyEncoding::Json => parameters.tojson(),
+            BodyEncoding::Form => parameters.toform()
+        }
     }
-}
\ No newline at end of file
+}
+
+fn form_value(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref text) => text.clone(),
+        ref other => other.to_string()
+    }
+}
+
+/// Sets `value` at a dotted `path` such as `address.city`, creating intermediate
+/// objects as needed. As with `add_parameter` the first value wins: a path that
+/// is already set, or runs through a non-object value, is left alone.
+pub fn insert_path(parameters: &mut Map<String,Value>, path: &str, value: Value) {
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let last = match keys.pop() {
+        Some(last) => last,
+        None => return
+    };
+
+    let mut current = parameters;
+    for key in keys {
+        let entry = current.entry(key.to_string()).or_insert_with(|| Value::Object(Map::new()));
+        current = match *entry {
+            Value::Object(ref mut map) => map,
+            _ => return
+        };
+    }
+    current.entry(last.to_string()).or_insert(value);
+}
+
+#[test]
+fn form_encoding() {
+    let mut parameters = Map::new();
+    parameters.insert("title".to_string(), Value::from("foo bar"));
+    parameters.insert("body".to_string(), Value::from("a&b=c"));
+    parameters.insert("userId".to_string(), Value::from(1));
+
+    assert_eq!(BodyEncoding::Form.encode(&parameters), "body=a%26b%3Dc&title=foo+bar&userId=1");
+}
+
+#[test]
+fn json_path() {
+    let mut parameters = Map::new();
+    insert_path(&mut parameters, "name", Value::from("foo"));
+    insert_path(&mut parameters, "address.city", Value::from("X"));
+    insert_path(&mut parameters, "address.geo.lat", Value::from(1.5));
+    insert_path(&mut parameters, "address.city", Value::from("Y"));
+    insert_path(&mut parameters, "name.first", Value::from("bar"));
+
+    assert_eq!(BodyEncoding::Json.encode(&parameters),
+        r#"{"address":{"city":"X","geo":{"lat":1.5}},"name":"foo"}"#);
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use base64;
+use hyper::net::{HttpStream, HttpsConnector, Openssl, OpensslStream, Ssl};
+use openssl::crypto::hash::{hash, Type};
+use openssl::nid::Nid;
+use openssl::ssl::{SslContext, SslMethod, SSL_VERIFY_NONE, SSL_VERIFY_PEER};
+use openssl::ssl::{SSL_OP_NO_COMPRESSION, SSL_OP_NO_SSLV2, SSL_OP_NO_SSLV3, SSL_OP_NO_TLSV1, SSL_OP_NO_TLSV1_1};
+use openssl::x509::{X509, X509FileType, X509StoreContext};
+
+use error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsVersion
+{
+    Tls1_0,
+    Tls1_1,
+    Tls1_2
+}
+
+/// TLS settings for an endpoint, see `EndpointBuilder::tls`.
+#[derive(Clone)]
+pub struct Tls {
+    settings: Arc<TlsBuilder>,
+    /// One context per server name, as the verify callback checks the certificate against it.
+    contexts: Arc<Mutex<HashMap<String, Openssl>>>
+}
+
+#[derive(Clone)]
+pub struct TlsBuilder {
+    roots: Vec<PathBuf>,
+    identity: Option<(PathBuf, PathBuf)>,
+    pins: Vec<String>,
+    min_version: TlsVersion,
+    accept_invalid_certs: bool
+}
+
+impl Tls {
+    pub fn configure() -> TlsBuilder {
+        TlsBuilder {
+            roots: Vec::new(),
+            identity: None,
+            pins: Vec::new(),
+            min_version: TlsVersion::Tls1_0,
+            accept_invalid_certs: false
+        }
+    }
+
+    pub fn connector(&self) -> HttpsConnector<Tls> {
+        HttpsConnector::new(self.clone())
+    }
+
+    fn openssl(&self, host: &str) -> Result<Openssl, Error> {
+        let mut contexts = self.contexts.lock().unwrap();
+        if let Some(openssl) = contexts.get(host) {
+            return Ok(openssl.clone());
+        }
+        let openssl = Openssl { context: Arc::new(try!(self.settings.context(Some(host)))) };
+        contexts.insert(host.to_string(), openssl.clone());
+        Ok(openssl)
+    }
+}
+
+/// The system roots with certificate and host name checks, for endpoints without `EndpointBuilder::tls`.
+impl Default for Tls {
+    fn default() -> Tls {
+        Tls::configure().build().expect("default TLS settings")
+    }
+}
+
+impl Ssl for Tls {
+    type Stream = OpensslStream<HttpStream>;
+
+    fn wrap_client(&self, stream: HttpStream, host: &str) -> ::hyper::Result<OpensslStream<HttpStream>> {
+        match self.openssl(host) {
+            Ok(openssl) => openssl.wrap_client(stream, host),
+            Err(err) => Err(::hyper::Error::Io(io::Error::new(io::ErrorKind::Other, err.to_string())))
+        }
+    }
+}
+
+impl TlsBuilder {
+    /// Trusts the CA certificates in a PEM file on top of the system ones.
+    pub fn add_root_certificate<P: AsRef<Path>>(&mut self, pem: P) -> &mut TlsBuilder {
+        self.roots.push(pem.as_ref().to_path_buf());
+        self
+    }
+
+    /// Presents a client certificate for mutual TLS, both as PEM files.
+    pub fn client_certificate<P: AsRef<Path>>(&mut self, cert: P, key: P) -> &mut TlsBuilder {
+        self.identity = Some((cert.as_ref().to_path_buf(), key.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Only accepts servers whose certificate key has this SHA-256 SPKI hash,
+    /// base64 encoded as in `openssl x509 -pubkey | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`.
+    /// A `sha256/` prefix is accepted; adding several pins allows any of them.
+    pub fn pin_spki(&mut self, pin: &str) -> &mut TlsBuilder {
+        self.pins.push(pin.trim().trim_left_matches("sha256/").to_string());
+        self
+    }
+
+    pub fn min_version(&mut self, version: TlsVersion) -> &mut TlsBuilder {
+        self.min_version = version;
+        self
+    }
+
+    /// Turns off certificate and host name verification. Only ever for local
+    /// testing against self-signed servers; pins are still enforced.
+    pub fn danger_accept_invalid_certs(&mut self, accept: bool) -> &mut TlsBuilder {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Loads the certificates and keys, failing with `Error::Tls` if any of them can't be used.
+    pub fn build(&self) -> Result<Tls, Error> {
+        try!(self.context(None));
+        if self.accept_invalid_certs {
+            warn!("TLS certificate verification is disabled");
+        }
+        Ok(Tls {
+            settings: Arc::new(self.clone()),
+            contexts: Arc::new(Mutex::new(HashMap::new()))
+        })
+    }
+
+    /// A context for connecting to `host`; without one it only proves the settings load.
+    fn context(&self, host: Option<&str>) -> Result<SslContext, Error> {
+        let mut context = try!(SslContext::new(SslMethod::Sslv23).map_err(tls_error));
+        try!(context.set_default_verify_paths().map_err(tls_error));
+        for root in self.roots.iter() {
+            try!(context.set_CA_file(root).map_err(tls_error));
+        }
+        if let Some((ref cert, ref key)) = self.identity {
+            try!(context.set_certificate_file(cert, X509FileType::PEM).map_err(tls_error));
+            try!(context.set_private_key_file(key, X509FileType::PEM).map_err(tls_error));
+            try!(context.check_private_key().map_err(tls_error));
+        }
+        context.set_options(disabled_versions(self.min_version) | SSL_OP_NO_COMPRESSION);
+
+        let pins = self.pins.clone();
+        let accept_invalid_certs = self.accept_invalid_certs;
+        let host = host.map(|host| host.trim_matches(|c| c == '[' || c == ']').to_lowercase());
+        let mode = if accept_invalid_certs && pins.is_empty() { SSL_VERIFY_NONE } else { SSL_VERIFY_PEER };
+        context.set_verify_callback(mode, move |preverified: bool, store: &X509StoreContext| {
+            if !preverified && !accept_invalid_certs {
+                return false;
+            }
+            // the chain is walked from the root down, the server's own certificate comes last
+            if store.error_depth() != 0 {
+                return true;
+            }
+            let cert = match store.current_cert() {
+                Some(cert) => cert,
+                None => return false
+            };
+            // OpenSSL only checks the chain, the name it was issued for is up to us
+            if !accept_invalid_certs && !host.as_ref().map_or(false, |host| matches_host(&cert, host)) {
+                return false;
+            }
+            pins.is_empty() || pins.contains(&spki_hash(&cert.public_key().save_pub()))
+        });
+        Ok(context)
+    }
+}
+
+/// Checks `host` against the certificate's DNS and IP subject alternative names,
+/// or its common name when it has none.
+fn matches_host(cert: &X509, host: &str) -> bool {
+    let ip = host.parse::<IpAddr>().ok();
+    match cert.subject_alt_names() {
+        Some(names) => names.iter().any(|name| match ip {
+            Some(ref ip) => name.ipaddress().map_or(false, |bytes| bytes == &ip_octets(ip)[..]),
+            None => name.dnsname().map_or(false, |pattern| matches_name(pattern, host))
+        }),
+        None => ip.is_none() && cert.subject_name().text_by_nid(Nid::CN).map_or(false, |cn| matches_name(&cn, host))
+    }
+}
+
+/// A leading `*.` stands for exactly one label, as in RFC 6125.
+fn matches_name(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim_right_matches('.').to_lowercase();
+    let host = host.trim_right_matches('.');
+    if pattern.starts_with("*.") {
+        match host.find('.') {
+            Some(dot) => dot > 0 && host[dot..] == pattern[1..],
+            None => false
+        }
+    } else {
+        pattern == host
+    }
+}
+
+fn ip_octets(ip: &IpAddr) -> Vec<u8> {
+    match *ip {
+        IpAddr::V4(ref ip) => ip.octets().to_vec(),
+        IpAddr::V6(ref ip) => ip.octets().to_vec()
+    }
+}
+
+fn disabled_versions(min_version: TlsVersion) -> ::openssl::ssl::SslContextOptions {
+    let mut options = SSL_OP_NO_SSLV2 | SSL_OP_NO_SSLV3;
+    if min_version != TlsVersion::Tls1_0 {
+        options = options | SSL_OP_NO_TLSV1;
+    }
+    if min_version == TlsVersion::Tls1_2 {
+        options = options | SSL_OP_NO_TLSV1_1;
+    }
+    options
+}
+
+/// base64(sha256(SubjectPublicKeyInfo))
+fn spki_hash(der: &[u8]) -> String {
+    base64::encode(&hash(Type::SHA256, der))
+}
+
+fn tls_error<E: ::std::fmt::Display>(err: E) -> Error {
+    Error::Tls(err.to_string())
+}
+
+#[test]
+fn tls_builder_pins() {
+    let mut builder = Tls::configure();
+    builder.pin_spki("sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=").pin_spki(" abc= ");
+
+    assert_eq!(builder.pins, vec!["47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_string(), "abc=".to_string()]);
+    assert_eq!(spki_hash(b""), "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=");
+}
+
+#[test]
+fn tls_min_version() {
+    assert_eq!(disabled_versions(TlsVersion::Tls1_2), SSL_OP_NO_SSLV2 | SSL_OP_NO_SSLV3 | SSL_OP_NO_TLSV1 | SSL_OP_NO_TLSV1_1);
+    assert!(!disabled_versions(TlsVersion::Tls1_0).contains(SSL_OP_NO_TLSV1));
+}
+
+#[test]
+fn tls_host_names() {
+    use std::fs::File;
+
+    assert!(matches_name("*.example.com", "api.example.com"));
+    assert!(matches_name("API.example.com.", "api.example.com"));
+    assert!(!matches_name("*.example.com", "example.com"));
+    assert!(!matches_name("*.example.com", "a.b.example.com"));
+
+    let cert = X509::from_pem(&mut File::open("tests/tls/server.pem").unwrap()).unwrap();
+    assert!(matches_host(&cert, "localhost"));
+    assert!(!matches_host(&cert, "127.0.0.1"));
+    assert!(!matches_host(&cert, "example.com"));
+}
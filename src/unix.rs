@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use hyper::client::{Connect, Registration};
+use hyper::net::Transport;
+use mio::{Evented, Poll, PollOpt, Ready, Token};
+use mio::unix::EventedFd;
+use url::Url;
+
+/// A non-blocking Unix domain socket that hyper's event loop can drive.
+#[derive(Debug)]
+pub struct UnixTransport(UnixStream);
+
+impl Read for UnixTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for UnixTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Evented for UnixTransport {
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).register(poll, token, interest, opts)
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).deregister(poll)
+    }
+}
+
+impl Transport for UnixTransport {
+    fn take_socket_error(&mut self) -> io::Result<()> {
+        match self.0.take_error() {
+            Ok(None) => Ok(()),
+            Ok(Some(err)) | Err(err) => Err(err)
+        }
+    }
+}
+
+/// Dials a Unix domain socket for every request, whatever host the URL names,
+/// so routes and the `Host` header work as they do over TCP.
+pub struct UnixConnector {
+    path: PathBuf,
+    connected: VecDeque<((&'static str, String, u16), io::Result<UnixTransport>)>
+}
+
+impl UnixConnector {
+    pub fn new<P: AsRef<Path>>(path: P) -> UnixConnector {
+        UnixConnector {
+            path: path.as_ref().to_path_buf(),
+            connected: VecDeque::new()
+        }
+    }
+
+    fn dial(&self) -> io::Result<UnixTransport> {
+        debug!("Connecting to {}", self.path.display());
+        let stream = try!(UnixStream::connect(&self.path));
+        try!(stream.set_nonblocking(true));
+        Ok(UnixTransport(stream))
+    }
+}
+
+impl Connect for UnixConnector {
+    type Output = UnixTransport;
+    type Key = (&'static str, String, u16);
+
+    fn key(&self, _: &Url) -> Option<Self::Key> {
+        Some(("unix", self.path.to_string_lossy().into_owned(), 0))
+    }
+
+    fn connect(&mut self, url: &Url) -> io::Result<Self::Key> {
+        let key = try!(self.key(url).ok_or(io::Error::new(io::ErrorKind::InvalidInput, "unsupported url")));
+        let stream = self.dial();
+        self.connected.push_back((key.clone(), stream));
+        Ok(key)
+    }
+
+    fn connected(&mut self) -> Option<(Self::Key, io::Result<Self::Output>)> {
+        self.connected.pop_front()
+    }
+
+    fn register(&mut self, _: Registration) {}
+}
+
+#[test]
+fn unix_connector_dials_socket() {
+    use std::env;
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+    use time;
+
+    let path = env::temp_dir().join(format!("rest-{}.sock", time::precise_time_ns()));
+    let listener = UnixListener::bind(&path).unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 4];
+        stream.read_exact(&mut request).unwrap();
+        request
+    });
+
+    let mut connector = UnixConnector::new(&path);
+    let key = connector.connect(&Url::parse("http://localhost/containers/json").unwrap()).unwrap();
+    let (connected, stream) = connector.connected().unwrap();
+    assert_eq!(connected, key);
+    stream.unwrap().write_all(b"GET ").unwrap();
+
+    assert_eq!(&server.join().unwrap(), b"GET ");
+    fs::remove_file(&path).unwrap();
+}
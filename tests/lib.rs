@@ -1,3 +1,4 @@
+extern crate openssl;
 extern crate rest;
 
 #[cfg(test)]
@@ -13,7 +14,7 @@ mod tests {
         let api = rest::Endpoint::configure()
             .url(URL)
             .timeout(Duration::from_secs(5))
-            .add_header("Accept-Encoding", "Accept-Encoding: gzip, deflate")
+            .add_header("Accept-Encoding", "gzip, deflate")
             .build();
 
         let request = rest::Request::get()
@@ -168,7 +169,7 @@ r#"[
             .build();
 
         let request = rest::Request::put()
-            .path("posts")
+            .path("posts/1")
             .add_parameter("title", "bar")
             .add_parameter("body", "foo")
             .add_parameter("userId", "1")
@@ -213,4 +214,276 @@ r#"[
         println!("{}",response.unwrap());
 
     }
+
+    #[test]
+    fn rest_error_status() {
+        let api = rest::Endpoint::configure()
+            .url(URL)
+            .timeout(Duration::from_secs(5))
+            .build();
+
+        let request = rest::Request::get()
+            .path("posts/{id}")
+            .add_urlsegment("id", "0")
+            .build();
+
+        match rest::Client::execute(&api, &request) {
+            Err(rest::Error::Status(404, _)) => {},
+            other => panic!("expected a 404 error, got {:?}", other)
+        }
+        match rest::Client::stream(&api, &request) {
+            Err(rest::Error::Status(404, _)) => {},
+            other => panic!("expected a 404 error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rest_http_proxy_forwards() {
+        let (addr, server) = http_server();
+        let proxy = format!("http://user:pass@{}", addr);
+
+        let api = rest::Endpoint::configure()
+            .url("http://example.com/")
+            .timeout(Duration::from_secs(5))
+            .proxy(&proxy)
+            .unwrap()
+            .build();
+        let request = rest::Request::get().path("posts").build();
+        assert_eq!(rest::Client::execute(&api, &request).unwrap(), "ok");
+
+        let head = server.join().unwrap();
+        assert!(head.starts_with("GET http://example.com/posts HTTP/1.1\r\n"));
+        assert!(head.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+    }
+
+    #[test]
+    fn rest_invalid_proxy() {
+        match rest::Endpoint::configure().proxy("https://proxy.corp") {
+            Err(rest::Error::InvalidProxy(ref url)) if url == "https://proxy.corp" => {},
+            other => panic!("expected an invalid proxy error, got {:?}", other.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn rest_unix_socket() {
+        use std::env;
+        use std::fs;
+        use std::os::unix::net::UnixListener;
+        use std::thread;
+
+        let path = env::temp_dir().join(format!("rest-test-{}.sock", ::std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || answer_ok(listener.accept().unwrap().0));
+
+        let api = rest::Endpoint::configure()
+            .url("http://docker/v1.24/")
+            .timeout(Duration::from_secs(5))
+            .unix_socket(&path)
+            .build();
+        let request = rest::Request::get()
+            .path("containers/{id}/json")
+            .add_urlsegment("id", "web")
+            .add_parameter("size", "1")
+            .build();
+        assert_eq!(rest::Client::execute(&api, &request).unwrap(), "ok");
+
+        let head = server.join().unwrap();
+        assert!(head.starts_with("GET /v1.24/containers/web/json?size=1 HTTP/1.1\r\n"));
+        assert!(head.contains("Host: docker\r\n"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rest_resolve_keeps_host() {
+        let (addr, server) = http_server();
+
+        // the override applies to hosts a proxy is bypassed for as well
+        let api = rest::Endpoint::configure()
+            .url(&format!("http://api.example.com:{}/", addr.port()))
+            .timeout(Duration::from_secs(5))
+            .resolve("api.example.com", addr)
+            .proxy("http://127.0.0.1:9")
+            .unwrap()
+            .no_proxy("api.example.com")
+            .build();
+        let request = rest::Request::get().path("users").build();
+        assert_eq!(rest::Client::execute(&api, &request).unwrap(), "ok");
+
+        let head = server.join().unwrap();
+        assert!(head.starts_with("GET /users HTTP/1.1\r\n"));
+        assert!(head.contains(&format!("Host: api.example.com:{}\r\n", addr.port())));
+    }
+
+    /// Reads one request head from `stream` and answers it with `ok`.
+    fn answer_ok<S: ::std::io::Read + ::std::io::Write>(mut stream: S) -> String {
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+            head.push(byte[0]);
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok").unwrap();
+        String::from_utf8_lossy(&head).into_owned()
+    }
+
+    /// A server on a free local port that answers one request with `ok`.
+    /// Joining it gives the request head it received.
+    fn http_server() -> (::std::net::SocketAddr, ::std::thread::JoinHandle<String>) {
+        use std::net::TcpListener;
+        use std::thread;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || answer_ok(listener.accept().unwrap().0));
+        (addr, server)
+    }
+
+    /// Serves `ok` over TLS with the certificates in `tests/tls`, optionally
+    /// requiring a client certificate signed by the test CA. The server name
+    /// each client asked for is sent to the returned receiver.
+    fn tls_server(method: ::openssl::ssl::SslMethod, require_client_cert: bool) -> (u16, ::std::sync::mpsc::Receiver<Option<String>>) {
+        use openssl::ssl::{SslContext, SslStream, SSL_VERIFY_FAIL_IF_NO_PEER_CERT, SSL_VERIFY_PEER};
+        use openssl::x509::{X509FileType, X509StoreContext};
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        use std::sync::mpsc;
+        use std::thread;
+
+        let mut context = SslContext::new(method).unwrap();
+        context.set_certificate_file("tests/tls/server.pem", X509FileType::PEM).unwrap();
+        context.set_private_key_file("tests/tls/server.key", X509FileType::PEM).unwrap();
+        if require_client_cert {
+            context.set_CA_file("tests/tls/ca.pem").unwrap();
+            context.set_verify_callback(SSL_VERIFY_PEER | SSL_VERIFY_FAIL_IF_NO_PEER_CERT,
+                                        |preverified: bool, _: &X509StoreContext| preverified);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (names, received) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match SslStream::accept(&context, stream.unwrap()) {
+                    Ok(stream) => stream,
+                    Err(_) => continue
+                };
+                let _ = names.send(stream.ssl().get_servername());
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                    request.push(byte[0]);
+                }
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok");
+            }
+        });
+        (port, received)
+    }
+
+    /// An endpoint for `https://host:port/` that reaches the local test server whatever `host` is.
+    fn tls_endpoint(host: &str, port: u16, tls: rest::Tls) -> rest::Endpoint {
+        rest::Endpoint::configure()
+            .url(&format!("https://{}:{}/", host, port))
+            .timeout(Duration::from_secs(5))
+            .resolve(host, format!("127.0.0.1:{}", port).parse().unwrap())
+            .tls(tls)
+            .build()
+    }
+
+    #[test]
+    fn rest_tls_root_certificate() {
+        use openssl::ssl::SslMethod;
+
+        let (port, names) = tls_server(SslMethod::Sslv23, false);
+        let request = rest::Request::get().path("").build();
+
+        let strict = tls_endpoint("localhost", port, rest::Tls::configure().build().unwrap());
+        assert!(rest::Client::execute(&strict, &request).is_err());
+
+        let tls = rest::Tls::configure()
+            .add_root_certificate("tests/tls/ca.pem")
+            .build()
+            .unwrap();
+        let trusted = tls_endpoint("localhost", port, tls.clone());
+        assert_eq!(rest::Client::execute(&trusted, &request).unwrap(), "ok");
+        // the resolver override changes where to connect, not the name asked for
+        assert_eq!(names.try_iter().last(), Some(Some("localhost".to_string())));
+
+        // the certificate is only good for localhost
+        let wrong_host = tls_endpoint("example.com", port, tls);
+        assert!(rest::Client::execute(&wrong_host, &request).is_err());
+
+        let insecure = rest::Tls::configure()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        let insecure = tls_endpoint("example.com", port, insecure);
+        assert_eq!(rest::Client::execute(&insecure, &request).unwrap(), "ok");
+    }
+
+    #[test]
+    fn rest_tls_pins() {
+        use openssl::ssl::SslMethod;
+        use std::fs::File;
+        use std::io::Read;
+
+        let (port, _) = tls_server(SslMethod::Sslv23, false);
+        let request = rest::Request::get().path("").build();
+        let mut pin = String::new();
+        File::open("tests/tls/server.pin").unwrap().read_to_string(&mut pin).unwrap();
+
+        let pinned = rest::Tls::configure()
+            .add_root_certificate("tests/tls/ca.pem")
+            .pin_spki(&pin)
+            .build()
+            .unwrap();
+        let pinned = tls_endpoint("localhost", port, pinned);
+        assert_eq!(rest::Client::execute(&pinned, &request).unwrap(), "ok");
+
+        let mismatch = rest::Tls::configure()
+            .add_root_certificate("tests/tls/ca.pem")
+            .pin_spki("sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=")
+            .build()
+            .unwrap();
+        let mismatch = tls_endpoint("localhost", port, mismatch);
+        assert!(rest::Client::execute(&mismatch, &request).is_err());
+    }
+
+    #[test]
+    fn rest_tls_client_certificate() {
+        use openssl::ssl::SslMethod;
+
+        let (port, _) = tls_server(SslMethod::Sslv23, true);
+        let request = rest::Request::get().path("").build();
+
+        let anonymous = rest::Tls::configure()
+            .add_root_certificate("tests/tls/ca.pem")
+            .build()
+            .unwrap();
+        let anonymous = tls_endpoint("localhost", port, anonymous);
+        assert!(rest::Client::execute(&anonymous, &request).is_err());
+
+        let mutual = rest::Tls::configure()
+            .add_root_certificate("tests/tls/ca.pem")
+            .client_certificate("tests/tls/client.pem", "tests/tls/client.key")
+            .build()
+            .unwrap();
+        let mutual = tls_endpoint("localhost", port, mutual);
+        assert_eq!(rest::Client::execute(&mutual, &request).unwrap(), "ok");
+    }
+
+    #[test]
+    fn rest_tls_min_version() {
+        use openssl::ssl::SslMethod;
+
+        let (port, _) = tls_server(SslMethod::Tlsv1, false);
+        let request = rest::Request::get().path("").build();
+
+        let modern = rest::Tls::configure()
+            .add_root_certificate("tests/tls/ca.pem")
+            .min_version(rest::TlsVersion::Tls1_2)
+            .build()
+            .unwrap();
+        let modern = tls_endpoint("localhost", port, modern);
+        assert!(rest::Client::execute(&modern, &request).is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,24 @@
+#!/bin/sh
+# Regenerates the certificates used by the TLS tests: a CA, a server
+# certificate for `localhost` only and a client certificate, all signed by the CA.
+set -e
+cd "$(dirname "$0")"
+
+openssl req -x509 -newkey rsa:2048 -nodes -days 36500 -subj "/CN=rest test CA" \
+    -keyout ca.key -out ca.pem
+
+openssl req -newkey rsa:2048 -nodes -subj "/CN=localhost" -keyout server.key -out server.csr
+printf "subjectAltName=DNS:localhost\nextendedKeyUsage=serverAuth\n" > server.ext
+openssl x509 -req -in server.csr -CA ca.pem -CAkey ca.key -CAcreateserial -days 36500 \
+    -extfile server.ext -out server.pem
+
+openssl req -newkey rsa:2048 -nodes -subj "/CN=rest test client" -keyout client.key -out client.csr
+printf "extendedKeyUsage=clientAuth\n" > client.ext
+openssl x509 -req -in client.csr -CA ca.pem -CAkey ca.key -CAcreateserial -days 36500 \
+    -extfile client.ext -out client.pem
+
+rm -f server.csr server.ext client.csr client.ext ca.srl
+
+# the SPKI pin of the server certificate, see `TlsBuilder::pin_spki`
+openssl x509 -in server.pem -pubkey -noout | openssl pkey -pubin -outform der \
+    | openssl dgst -sha256 -binary | base64 > server.pin